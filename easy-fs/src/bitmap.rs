@@ -0,0 +1,91 @@
+use super::{get_block_cache, BlockDevice, BLOCK_SZ};
+use alloc::sync::Arc;
+
+/// A block of bitmap bits, stored as 64-bit words.
+type BitmapBlock = [u64; 64];
+
+const BLOCK_BITS: usize = BLOCK_SZ * 8;
+
+/// An on-disk bitmap spanning `blocks` consecutive blocks starting at
+/// `start_block_id`, used to allocate/free data blocks or inodes.
+pub struct Bitmap {
+    start_block_id: usize,
+    blocks: usize,
+}
+
+/// Decompose a global bit position into (block index within the bitmap,
+/// word index within that block, bit index within that word).
+fn decompose(bit: usize) -> (usize, usize, usize) {
+    let block = bit / BLOCK_BITS;
+    let rest = bit % BLOCK_BITS;
+    (block, rest / 64, rest % 64)
+}
+
+impl Bitmap {
+    /// Create a new bitmap covering `blocks` blocks starting at
+    /// `start_block_id`.
+    pub fn new(start_block_id: usize, blocks: usize) -> Self {
+        Self {
+            start_block_id,
+            blocks,
+        }
+    }
+
+    /// Allocate the first free bit, returning its global position, or
+    /// `None` if the bitmap is full.
+    pub fn alloc(&self, block_device: &Arc<dyn BlockDevice>) -> Option<usize> {
+        for block_id in 0..self.blocks {
+            let pos = get_block_cache(
+                block_id + self.start_block_id,
+                Arc::clone(block_device),
+            )
+            .lock()
+            .modify(0, |bitmap_block: &mut BitmapBlock| {
+                if let Some((word_idx, word)) = bitmap_block
+                    .iter_mut()
+                    .enumerate()
+                    .find(|(_, word)| **word != u64::MAX)
+                {
+                    let bit_idx = word.trailing_ones() as usize;
+                    *word |= 1u64 << bit_idx;
+                    Some(block_id * BLOCK_BITS + word_idx * 64 + bit_idx)
+                } else {
+                    None
+                }
+            });
+            if pos.is_some() {
+                return pos;
+            }
+        }
+        None
+    }
+
+    /// Free the bit at global position `bit`.
+    pub fn dealloc(&self, block_device: &Arc<dyn BlockDevice>, bit: usize) {
+        let (block, word_idx, bit_idx) = decompose(bit);
+        get_block_cache(block + self.start_block_id, Arc::clone(block_device))
+            .lock()
+            .modify(0, |bitmap_block: &mut BitmapBlock| {
+                assert!(bitmap_block[word_idx] & (1u64 << bit_idx) > 0);
+                bitmap_block[word_idx] -= 1u64 << bit_idx;
+            });
+    }
+
+    /// The maximum number of bits this bitmap can hold.
+    pub fn maximum(&self) -> usize {
+        self.blocks * BLOCK_BITS
+    }
+
+    /// Count how many bits are currently set (allocated).
+    pub fn count_allocated(&self, block_device: &Arc<dyn BlockDevice>) -> usize {
+        let mut count = 0;
+        for block_id in 0..self.blocks {
+            count += get_block_cache(block_id + self.start_block_id, Arc::clone(block_device))
+                .lock()
+                .read(0, |bitmap_block: &BitmapBlock| {
+                    bitmap_block.iter().map(|w| w.count_ones() as usize).sum::<usize>()
+                });
+        }
+        count
+    }
+}
@@ -0,0 +1,227 @@
+use super::{BlockDevice, BLOCK_SZ};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use lazy_static::*;
+use spin::Mutex;
+
+/// The number of `BlockCache` entries kept resident at once.
+pub const BLOCK_CACHE_SIZE: usize = 16;
+
+/// A cached copy of one on-disk block, flushed back lazily (on eviction or
+/// on an explicit `sync`).
+pub struct BlockCache {
+    /// cached block data
+    cache: [u8; BLOCK_SZ],
+    /// underlying block id
+    block_id: usize,
+    /// underlying block device
+    block_device: Arc<dyn BlockDevice>,
+    /// whether this block has been modified since it was read in
+    modified: bool,
+}
+
+impl BlockCache {
+    /// Load a new BlockCache from disk.
+    pub fn new(block_id: usize, block_device: Arc<dyn BlockDevice>) -> Self {
+        let mut cache = [0u8; BLOCK_SZ];
+        block_device.read_block(block_id, &mut cache);
+        Self {
+            cache,
+            block_id,
+            block_device,
+            modified: false,
+        }
+    }
+
+    fn addr_of_offset(&self, offset: usize) -> usize {
+        &self.cache[offset] as *const _ as usize
+    }
+
+    /// Get a reference to whatever `T` lives at `offset` within this block.
+    pub fn get_ref<T>(&self, offset: usize) -> &T
+    where
+        T: Sized,
+    {
+        let type_size = core::mem::size_of::<T>();
+        assert!(offset + type_size <= BLOCK_SZ);
+        let addr = self.addr_of_offset(offset);
+        unsafe { &*(addr as *const T) }
+    }
+
+    /// Get a mutable reference to whatever `T` lives at `offset` within this
+    /// block, marking the block dirty.
+    pub fn get_mut<T>(&mut self, offset: usize) -> &mut T
+    where
+        T: Sized,
+    {
+        let type_size = core::mem::size_of::<T>();
+        assert!(offset + type_size <= BLOCK_SZ);
+        self.modified = true;
+        let addr = self.addr_of_offset(offset);
+        unsafe { &mut *(addr as *mut T) }
+    }
+
+    /// Call `f` over a `&T` at `offset`
+    pub fn read<T, V>(&self, offset: usize, f: impl FnOnce(&T) -> V) -> V {
+        f(self.get_ref(offset))
+    }
+
+    /// Call `f` over a `&mut T` at `offset`
+    pub fn modify<T, V>(&mut self, offset: usize, f: impl FnOnce(&mut T) -> V) -> V {
+        f(self.get_mut(offset))
+    }
+
+    /// Flush this block back to the block device if it was modified.
+    pub fn sync(&mut self) {
+        if self.modified {
+            self.modified = false;
+            self.block_device.write_block(self.block_id, &self.cache);
+        }
+    }
+}
+
+impl Drop for BlockCache {
+    fn drop(&mut self) {
+        self.sync();
+    }
+}
+
+/// A fixed-capacity pool of resident `BlockCache`s, ordered least- to
+/// most-recently-used. When full, the least-recently-used entry whose only
+/// reference is the one held by this manager is evicted (and flushed via
+/// `Drop`) to make room.
+pub struct BlockCacheManager {
+    queue: VecDeque<(usize, Arc<Mutex<BlockCache>>)>,
+}
+
+impl BlockCacheManager {
+    /// Create an empty cache manager.
+    pub fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Get the `BlockCache` for `block_id`, loading it from `block_device`
+    /// if it isn't already resident. Either way, `block_id` becomes the
+    /// most-recently-used entry.
+    pub fn get_block_cache(
+        &mut self,
+        block_id: usize,
+        block_device: Arc<dyn BlockDevice>,
+    ) -> Arc<Mutex<BlockCache>> {
+        if let Some(idx) = self.queue.iter().position(|(id, _)| *id == block_id) {
+            let (id, cache) = self.queue.remove(idx).unwrap();
+            self.queue.push_back((id, Arc::clone(&cache)));
+            return cache;
+        }
+        if self.queue.len() == BLOCK_CACHE_SIZE {
+            // evict the least-recently-used entry that nobody else holds a
+            // reference to, scanning from the front of the queue
+            if let Some(idx) = self
+                .queue
+                .iter()
+                .position(|(_, cache)| Arc::strong_count(cache) == 1)
+            {
+                self.queue.remove(idx);
+            } else {
+                panic!("all block caches are in use, cannot evict any");
+            }
+        }
+        let block_cache = Arc::new(Mutex::new(BlockCache::new(
+            block_id,
+            Arc::clone(&block_device),
+        )));
+        self.queue.push_back((block_id, Arc::clone(&block_cache)));
+        block_cache
+    }
+}
+
+lazy_static! {
+    /// The global block cache manager instance.
+    pub static ref BLOCK_CACHE_MANAGER: Mutex<BlockCacheManager> =
+        Mutex::new(BlockCacheManager::new());
+}
+
+/// Get the block cache for `block_id`, going through the global cache
+/// manager.
+pub fn get_block_cache(
+    block_id: usize,
+    block_device: Arc<dyn BlockDevice>,
+) -> Arc<Mutex<BlockCache>> {
+    BLOCK_CACHE_MANAGER
+        .lock()
+        .get_block_cache(block_id, block_device)
+}
+
+/// Flush every resident block cache back to the block device.
+pub fn block_cache_sync_all() {
+    let manager = BLOCK_CACHE_MANAGER.lock();
+    for (_, cache) in manager.queue.iter() {
+        cache.lock().sync();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::sync::Mutex as StdMutex;
+    use std::vec;
+    use std::vec::Vec;
+
+    /// An in-memory stand-in for a real block device, for exercising the
+    /// cache manager without any kernel/host I/O.
+    struct RamDisk(StdMutex<Vec<[u8; BLOCK_SZ]>>);
+
+    impl RamDisk {
+        fn new(blocks: usize) -> Self {
+            Self(StdMutex::new(vec![[0u8; BLOCK_SZ]; blocks]))
+        }
+    }
+
+    impl BlockDevice for RamDisk {
+        fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+            buf.copy_from_slice(&self.0.lock().unwrap()[block_id]);
+        }
+        fn write_block(&self, block_id: usize, buf: &[u8]) {
+            self.0.lock().unwrap()[block_id].copy_from_slice(buf);
+        }
+    }
+
+    #[test]
+    fn evicted_block_is_synced_and_reread_correctly() {
+        let device: Arc<dyn BlockDevice> = Arc::new(RamDisk::new(BLOCK_CACHE_SIZE + 1));
+        let mut manager = BlockCacheManager::new();
+        manager
+            .get_block_cache(0, Arc::clone(&device))
+            .lock()
+            .modify(0, |byte: &mut u8| *byte = 0x42);
+        // touch BLOCK_CACHE_SIZE other blocks without keeping any of them
+        // alive, pushing block 0 out as the least-recently-used entry
+        for block_id in 1..=BLOCK_CACHE_SIZE {
+            manager.get_block_cache(block_id, Arc::clone(&device));
+        }
+        let reread = manager
+            .get_block_cache(0, Arc::clone(&device))
+            .lock()
+            .read(0, |byte: &u8| *byte);
+        assert_eq!(reread, 0x42);
+    }
+
+    #[test]
+    fn recently_used_block_is_not_the_eviction_victim() {
+        let device: Arc<dyn BlockDevice> = Arc::new(RamDisk::new(BLOCK_CACHE_SIZE + 1));
+        let mut manager = BlockCacheManager::new();
+        for block_id in 0..BLOCK_CACHE_SIZE {
+            manager.get_block_cache(block_id, Arc::clone(&device));
+        }
+        // re-touch block 0, making it most-recently-used and block 1 the
+        // new least-recently-used entry
+        manager.get_block_cache(0, Arc::clone(&device));
+        manager.get_block_cache(BLOCK_CACHE_SIZE, Arc::clone(&device));
+        assert!(manager.queue.iter().any(|(id, _)| *id == 0));
+        assert!(!manager.queue.iter().any(|(id, _)| *id == 1));
+    }
+}
@@ -0,0 +1,11 @@
+use core::any::Any;
+
+/// A block device that can read/write a block (identified by its id) at a
+/// time. Implemented by the host environment (e.g. a virtio-blk driver);
+/// easy-fs only ever talks to this trait.
+pub trait BlockDevice: Send + Sync + Any {
+    /// Read the block numbered `block_id` into `buf`
+    fn read_block(&self, block_id: usize, buf: &mut [u8]);
+    /// Write `buf` into the block numbered `block_id`
+    fn write_block(&self, block_id: usize, buf: &[u8]);
+}
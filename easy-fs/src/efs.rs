@@ -0,0 +1,221 @@
+use super::{
+    block_cache_sync_all, get_block_cache, Bitmap, BlockDevice, DiskInode, DiskInodeType,
+    SuperBlock, BLOCK_SZ, NAME_LENGTH_LIMIT,
+};
+use crate::vfs::Inode;
+use alloc::sync::Arc;
+use spin::Mutex;
+
+/// The root inode always lives at inode id 0.
+const ROOT_INODE_ID: u32 = 0;
+
+/// Filesystem-wide usage statistics, as reported by [`EasyFileSystem::stat`].
+pub struct FsStat {
+    /// size, in bytes, of a single block
+    pub block_size: u32,
+    /// total number of blocks available to the data area
+    pub total_blocks: u32,
+    /// number of data blocks not currently allocated
+    pub free_blocks: u32,
+    /// total number of inodes the filesystem can hold
+    pub total_inodes: u32,
+    /// number of inodes not currently allocated
+    pub free_inodes: u32,
+    /// maximum length, in bytes, of a single filename component
+    pub name_max: u32,
+}
+
+/// Top-level easy-fs handle: owns the inode/data bitmaps and knows how to
+/// translate inode/data block ids into physical block ids.
+pub struct EasyFileSystem {
+    /// the block device this filesystem sits on
+    pub block_device: Arc<dyn BlockDevice>,
+    /// bitmap tracking which inodes are in use
+    pub inode_bitmap: Bitmap,
+    /// bitmap tracking which data blocks are in use
+    pub data_bitmap: Bitmap,
+    inode_area_start_block: u32,
+    data_area_start_block: u32,
+    // Incrementally maintained alongside `inode_bitmap`/`data_bitmap` so
+    // `stat` never has to rescan either bitmap.
+    inode_alloc_count: u32,
+    data_alloc_count: u32,
+}
+
+impl EasyFileSystem {
+    /// Format `block_device` as a fresh easy-fs filesystem of `total_blocks`
+    /// blocks, with `inode_bitmap_blocks` blocks of inode bitmap (and as many
+    /// inode blocks as that bitmap can address), and return a handle
+    /// wrapping it.
+    pub fn create(
+        block_device: Arc<dyn BlockDevice>,
+        total_blocks: u32,
+        inode_bitmap_blocks: u32,
+    ) -> Arc<Mutex<Self>> {
+        // calculate block size of areas & create bitmaps
+        let inode_bitmap = Bitmap::new(1, inode_bitmap_blocks as usize);
+        let inode_num = inode_bitmap.maximum();
+        let inode_area_blocks =
+            ((inode_num * core::mem::size_of::<DiskInode>()).div_ceil(BLOCK_SZ)) as u32;
+        let inode_total_blocks = inode_bitmap_blocks + inode_area_blocks;
+        let data_total_blocks = total_blocks - 1 - inode_total_blocks;
+        let data_bitmap_blocks = (data_total_blocks / (4096 + 1)) + 1;
+        let data_area_blocks = data_total_blocks - data_bitmap_blocks;
+        let data_bitmap = Bitmap::new((1 + inode_total_blocks) as usize, data_bitmap_blocks as usize);
+        let mut efs = Self {
+            block_device: Arc::clone(&block_device),
+            inode_bitmap,
+            data_bitmap,
+            inode_area_start_block: 1 + inode_bitmap_blocks,
+            data_area_start_block: 1 + inode_total_blocks + data_bitmap_blocks,
+            inode_alloc_count: 0,
+            data_alloc_count: 0,
+        };
+        // clear all blocks
+        for i in 0..total_blocks {
+            get_block_cache(i as usize, Arc::clone(&block_device))
+                .lock()
+                .modify(0, |data_block: &mut [u8; BLOCK_SZ]| {
+                    for byte in data_block.iter_mut() {
+                        *byte = 0;
+                    }
+                });
+        }
+        // initialize SuperBlock
+        get_block_cache(0, Arc::clone(&block_device)).lock().modify(
+            0,
+            |super_block: &mut SuperBlock| {
+                super_block.initialize(
+                    total_blocks,
+                    inode_bitmap_blocks,
+                    inode_area_blocks,
+                    data_bitmap_blocks,
+                    data_area_blocks,
+                );
+            },
+        );
+        // allocate and initialize the root inode as a directory
+        assert_eq!(efs.alloc_inode(), ROOT_INODE_ID);
+        let (root_block_id, root_block_offset) = efs.get_disk_inode_pos(ROOT_INODE_ID);
+        get_block_cache(root_block_id as usize, Arc::clone(&block_device))
+            .lock()
+            .modify(root_block_offset, |disk_inode: &mut DiskInode| {
+                // no wall-clock time is available this early in formatting
+                disk_inode.initialize(DiskInodeType::Directory, 0);
+            });
+        block_cache_sync_all();
+        Arc::new(Mutex::new(efs))
+    }
+
+    /// Open an existing easy-fs filesystem on `block_device`.
+    pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
+        let block_cache = get_block_cache(0, Arc::clone(&block_device));
+        let inner = block_cache.lock().read(0, |super_block: &SuperBlock| {
+            assert!(super_block.is_valid(), "Error loading EFS!");
+            let inode_total_blocks = super_block.inode_bitmap_blocks + super_block.inode_area_blocks;
+            let inode_bitmap = Bitmap::new(1, super_block.inode_bitmap_blocks as usize);
+            let data_bitmap = Bitmap::new(
+                (1 + inode_total_blocks) as usize,
+                super_block.data_bitmap_blocks as usize,
+            );
+            // one-time scan to bootstrap the cached alloc counts; every
+            // alloc/dealloc after this keeps them up to date incrementally
+            let inode_alloc_count = inode_bitmap.count_allocated(&block_device) as u32;
+            let data_alloc_count = data_bitmap.count_allocated(&block_device) as u32;
+            Self {
+                block_device: Arc::clone(&block_device),
+                inode_bitmap,
+                data_bitmap,
+                inode_area_start_block: 1 + super_block.inode_bitmap_blocks,
+                data_area_start_block: 1 + inode_total_blocks + super_block.data_bitmap_blocks,
+                inode_alloc_count,
+                data_alloc_count,
+            }
+        });
+        Arc::new(Mutex::new(inner))
+    }
+
+    /// The root directory inode, wrapped in the VFS layer.
+    pub fn root_inode(efs: &Arc<Mutex<Self>>) -> Inode {
+        let block_device = Arc::clone(&efs.lock().block_device);
+        let (block_id, block_offset) = efs.lock().get_disk_inode_pos(ROOT_INODE_ID);
+        let inode = Inode::new(block_id, block_offset, Arc::clone(efs), block_device);
+        inode.set_inode_id(ROOT_INODE_ID);
+        inode
+    }
+
+    /// Resolve an inode id to its (block id, offset within that block).
+    pub fn get_disk_inode_pos(&self, inode_id: u32) -> (u32, usize) {
+        let inode_size = core::mem::size_of::<DiskInode>();
+        let inodes_per_block = (BLOCK_SZ / inode_size) as u32;
+        let block_id = self.inode_area_start_block + inode_id / inodes_per_block;
+        (
+            block_id,
+            (inode_id % inodes_per_block) as usize * inode_size,
+        )
+    }
+
+    /// Resolve a data block index (relative to the start of the data area)
+    /// to a physical block id.
+    pub fn get_data_block_id(&self, data_block_id: u32) -> u32 {
+        self.data_area_start_block + data_block_id
+    }
+
+    /// Allocate a fresh inode, returning its inode id.
+    pub fn alloc_inode(&mut self) -> u32 {
+        let id = self.inode_bitmap.alloc(&self.block_device).unwrap() as u32;
+        self.inode_alloc_count += 1;
+        id
+    }
+
+    /// Allocate a fresh data block, returning its physical block id.
+    pub fn alloc_data(&mut self) -> u32 {
+        let id = self.data_area_start_block
+            + self.data_bitmap.alloc(&self.block_device).unwrap() as u32;
+        self.data_alloc_count += 1;
+        id
+    }
+
+    /// Free a data block, given its physical block id.
+    pub fn dealloc_data(&mut self, block_id: u32) {
+        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(0, |data_block: &mut [u8; BLOCK_SZ]| {
+                for byte in data_block.iter_mut() {
+                    *byte = 0;
+                }
+            });
+        self.data_bitmap.dealloc(
+            &self.block_device,
+            (block_id - self.data_area_start_block) as usize,
+        );
+        self.data_alloc_count -= 1;
+    }
+
+    /// Free an inode, given its inode id.
+    pub fn dealloc_inode(&mut self, inode_id: u32) {
+        self.inode_bitmap.dealloc(&self.block_device, inode_id as usize);
+        self.inode_alloc_count -= 1;
+    }
+
+    /// Filesystem usage statistics (block/inode totals and free counts).
+    /// Backed by [`Self::inode_alloc_count`]/[`Self::data_alloc_count`],
+    /// which are kept up to date by every alloc/dealloc, so this never
+    /// needs to rescan either bitmap.
+    pub fn stat(&self) -> FsStat {
+        let total_inodes = self.inode_bitmap.maximum() as u32;
+        // the data bitmap can address more bits than the data area has
+        // blocks, so the real total comes from the super block
+        let total_blocks = get_block_cache(0, Arc::clone(&self.block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| super_block.data_area_blocks);
+        FsStat {
+            block_size: BLOCK_SZ as u32,
+            total_blocks,
+            free_blocks: total_blocks - self.data_alloc_count,
+            total_inodes,
+            free_inodes: total_inodes - self.inode_alloc_count,
+            name_max: NAME_LENGTH_LIMIT as u32,
+        }
+    }
+}
@@ -0,0 +1,574 @@
+use super::{get_block_cache, BlockDevice, BLOCK_SZ};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Magic number used to sanity-check that a block device actually holds an
+/// easy-fs filesystem.
+pub const EFS_MAGIC: u32 = 0x3b80_0001;
+/// How many direct data-block pointers a `DiskInode` stores inline.
+const INODE_DIRECT_COUNT: usize = 28;
+/// Max filename length (not counting the NUL terminator written into the
+/// fixed-size `DirEntry::name` field).
+pub(crate) const NAME_LENGTH_LIMIT: usize = 27;
+/// How many block ids fit in one indirect block.
+const INDIRECT1_COUNT: usize = BLOCK_SZ / 4;
+const INDIRECT2_COUNT: usize = INDIRECT1_COUNT * INDIRECT1_COUNT;
+const INDIRECT3_COUNT: usize = INDIRECT2_COUNT * INDIRECT1_COUNT;
+
+const DIRECT_BOUND: usize = INODE_DIRECT_COUNT;
+const INDIRECT1_BOUND: usize = DIRECT_BOUND + INDIRECT1_COUNT;
+const INDIRECT2_BOUND: usize = INDIRECT1_BOUND + INDIRECT2_COUNT;
+const INDIRECT3_BOUND: usize = INDIRECT2_BOUND + INDIRECT3_COUNT;
+
+type IndirectBlock = [u32; BLOCK_SZ / 4];
+type DataBlock = [u8; BLOCK_SZ];
+
+/// The first block of an easy-fs filesystem, describing its overall layout.
+#[repr(C)]
+pub struct SuperBlock {
+    magic: u32,
+    /// total number of blocks in the filesystem, including this super block
+    pub total_blocks: u32,
+    /// number of blocks used by the inode bitmap
+    pub inode_bitmap_blocks: u32,
+    /// number of blocks used to store inodes
+    pub inode_area_blocks: u32,
+    /// number of blocks used by the data bitmap
+    pub data_bitmap_blocks: u32,
+    /// number of blocks used to store file data
+    pub data_area_blocks: u32,
+}
+
+impl SuperBlock {
+    /// Initialize a fresh super block describing the given layout.
+    pub fn initialize(
+        &mut self,
+        total_blocks: u32,
+        inode_bitmap_blocks: u32,
+        inode_area_blocks: u32,
+        data_bitmap_blocks: u32,
+        data_area_blocks: u32,
+    ) {
+        *self = Self {
+            magic: EFS_MAGIC,
+            total_blocks,
+            inode_bitmap_blocks,
+            inode_area_blocks,
+            data_bitmap_blocks,
+            data_area_blocks,
+        };
+    }
+
+    /// Whether this looks like a valid easy-fs super block.
+    pub fn is_valid(&self) -> bool {
+        self.magic == EFS_MAGIC
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+/// What kind of file a `DiskInode` describes.
+pub enum DiskInodeType {
+    /// a regular file
+    File,
+    /// a directory
+    Directory,
+}
+
+/// The on-disk inode: file/directory metadata plus the block pointers
+/// needed to resolve a logical byte offset to a physical data block.
+#[repr(C)]
+pub struct DiskInode {
+    /// file/directory size in bytes
+    pub size: u32,
+    /// direct data block pointers
+    pub direct: [u32; INODE_DIRECT_COUNT],
+    /// singly-indirect data block pointer
+    pub indirect1: u32,
+    /// doubly-indirect data block pointer
+    pub indirect2: u32,
+    /// triply-indirect data block pointer
+    pub indirect3: u32,
+    type_: DiskInodeType,
+    /// owning user id
+    pub uid: u32,
+    /// owning group id
+    pub gid: u32,
+    /// 9-bit rwx permission bits (owner/group/other), e.g. `0o644`
+    pub mode: u16,
+    /// last access time (in whatever unit the kernel's time source passes in)
+    pub atime: u64,
+    /// last content modification time
+    pub mtime: u64,
+    /// last metadata (inode) change time
+    pub ctime: u64,
+    /// link count: 1 on creation, incremented by `add_link` and decremented
+    /// by `remove_link`; a directory's `.`/`..` entries are not counted
+    pub nlink: u32,
+}
+
+/// Default permission bits handed out by [`DiskInode::initialize`] for a
+/// freshly created file.
+const DEFAULT_FILE_MODE: u16 = 0o644;
+/// Default permission bits handed out by [`DiskInode::initialize`] for a
+/// freshly created directory.
+const DEFAULT_DIR_MODE: u16 = 0o755;
+
+impl DiskInode {
+    /// Initialize an inode as empty, of the given type, owned by root
+    /// (uid/gid 0) with the type's default permission bits. `now` is the
+    /// current time (from the kernel's time source) used to stamp
+    /// atime/mtime/ctime.
+    pub fn initialize(&mut self, type_: DiskInodeType, now: u64) {
+        self.size = 0;
+        self.direct = [0; INODE_DIRECT_COUNT];
+        self.indirect1 = 0;
+        self.indirect2 = 0;
+        self.indirect3 = 0;
+        self.uid = 0;
+        self.gid = 0;
+        self.mode = match type_ {
+            DiskInodeType::Directory => DEFAULT_DIR_MODE,
+            DiskInodeType::File => DEFAULT_FILE_MODE,
+        };
+        self.atime = now;
+        self.mtime = now;
+        self.ctime = now;
+        self.nlink = 1;
+        self.type_ = type_;
+    }
+
+    /// Whether this inode describes a directory.
+    pub fn is_dir(&self) -> bool {
+        self.type_ == DiskInodeType::Directory
+    }
+    /// Whether this inode describes a regular file.
+    pub fn is_file(&self) -> bool {
+        self.type_ == DiskInodeType::File
+    }
+
+    /// Resolve the `inner_id`-th data block of this file to a physical
+    /// block id, walking through direct/indirect1/indirect2/indirect3 as
+    /// needed.
+    pub fn get_block_id(&self, inner_id: u32, block_device: &Arc<dyn BlockDevice>) -> u32 {
+        let inner_id = inner_id as usize;
+        if inner_id < INODE_DIRECT_COUNT {
+            self.direct[inner_id]
+        } else if inner_id < INDIRECT1_BOUND {
+            get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect_block: &IndirectBlock| {
+                    indirect_block[inner_id - DIRECT_BOUND]
+                })
+        } else if inner_id < INDIRECT2_BOUND {
+            let last = inner_id - INDIRECT1_BOUND;
+            let indirect1 = get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect2: &IndirectBlock| {
+                    indirect2[last / INDIRECT1_COUNT]
+                });
+            get_block_cache(indirect1 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect_block: &IndirectBlock| {
+                    indirect_block[last % INDIRECT1_COUNT]
+                })
+        } else {
+            let last = inner_id - INDIRECT2_BOUND;
+            let (a, rem) = (last / INDIRECT2_COUNT, last % INDIRECT2_COUNT);
+            let (b, c) = (rem / INDIRECT1_COUNT, rem % INDIRECT1_COUNT);
+            let indirect2 = get_block_cache(self.indirect3 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect3: &IndirectBlock| indirect3[a]);
+            let indirect1 = get_block_cache(indirect2 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect2: &IndirectBlock| indirect2[b]);
+            get_block_cache(indirect1 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect_block: &IndirectBlock| indirect_block[c])
+        }
+    }
+
+    fn data_blocks(size: u32) -> u32 {
+        (size as usize).div_ceil(BLOCK_SZ) as u32
+    }
+
+    /// How many blocks (data + whatever new index blocks are needed) a file
+    /// of `size` bytes occupies in total.
+    pub fn total_blocks(size: u32) -> u32 {
+        let data_blocks = Self::data_blocks(size) as usize;
+        let mut total = data_blocks;
+        // indirect1
+        if data_blocks > INODE_DIRECT_COUNT {
+            total += 1;
+        }
+        // indirect2
+        if data_blocks > INDIRECT1_BOUND {
+            total += 1;
+            let d2 = data_blocks.min(INDIRECT2_BOUND) - INDIRECT1_BOUND;
+            total += d2.div_ceil(INDIRECT1_COUNT);
+        }
+        // indirect3
+        if data_blocks > INDIRECT2_BOUND {
+            total += 1;
+            let d3 = data_blocks.min(INDIRECT3_BOUND) - INDIRECT2_BOUND;
+            total += d3.div_ceil(INDIRECT1_COUNT);
+            total += d3.div_ceil(INDIRECT2_COUNT);
+        }
+        total as u32
+    }
+
+    /// How many additional blocks are needed to grow this inode to
+    /// `new_size` bytes.
+    pub fn blocks_num_needed(&self, new_size: u32) -> u32 {
+        assert!(new_size >= self.size);
+        Self::total_blocks(new_size) - Self::total_blocks(self.size)
+    }
+
+    /// Grow this inode to `new_size` bytes, consuming block ids from
+    /// `new_blocks` (allocated by the caller) to fill in direct/indirect1/
+    /// indirect2/indirect3 pointers as needed.
+    pub fn increase_size(
+        &mut self,
+        new_size: u32,
+        new_blocks: Vec<u32>,
+        block_device: &Arc<dyn BlockDevice>,
+    ) {
+        let old_blocks = Self::data_blocks(self.size) as usize;
+        self.size = new_size;
+        let new_blocks_total = Self::data_blocks(self.size) as usize;
+        let mut pool = new_blocks.into_iter();
+
+        // direct range: [0, INODE_DIRECT_COUNT)
+        let hi0 = new_blocks_total.min(INODE_DIRECT_COUNT);
+        for i in old_blocks.min(INODE_DIRECT_COUNT)..hi0 {
+            self.direct[i] = pool.next().unwrap();
+        }
+        if new_blocks_total <= INODE_DIRECT_COUNT {
+            return;
+        }
+
+        // indirect1 range: [INODE_DIRECT_COUNT, INDIRECT1_BOUND)
+        if old_blocks <= INODE_DIRECT_COUNT {
+            self.indirect1 = pool.next().unwrap();
+        }
+        let lo1 = old_blocks.clamp(INODE_DIRECT_COUNT, INDIRECT1_BOUND) - INODE_DIRECT_COUNT;
+        let hi1 = new_blocks_total.min(INDIRECT1_BOUND) - INODE_DIRECT_COUNT;
+        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
+            .lock()
+            .modify(0, |indirect1: &mut IndirectBlock| {
+                for i in lo1..hi1 {
+                    indirect1[i] = pool.next().unwrap();
+                }
+            });
+        if new_blocks_total <= INDIRECT1_BOUND {
+            return;
+        }
+
+        // indirect2 range: [INDIRECT1_BOUND, INDIRECT2_BOUND)
+        if old_blocks <= INDIRECT1_BOUND {
+            self.indirect2 = pool.next().unwrap();
+        }
+        let lo2 = old_blocks.clamp(INDIRECT1_BOUND, INDIRECT2_BOUND) - INDIRECT1_BOUND;
+        let hi2 = new_blocks_total.min(INDIRECT2_BOUND) - INDIRECT1_BOUND;
+        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+            .lock()
+            .modify(0, |indirect2: &mut IndirectBlock| {
+                for i in lo2..hi2 {
+                    let (a, b) = (i / INDIRECT1_COUNT, i % INDIRECT1_COUNT);
+                    if b == 0 {
+                        indirect2[a] = pool.next().unwrap();
+                    }
+                    get_block_cache(indirect2[a] as usize, Arc::clone(block_device))
+                        .lock()
+                        .modify(0, |indirect1: &mut IndirectBlock| {
+                            indirect1[b] = pool.next().unwrap();
+                        });
+                }
+            });
+        if new_blocks_total <= INDIRECT2_BOUND {
+            return;
+        }
+
+        // indirect3 range: [INDIRECT2_BOUND, INDIRECT3_BOUND)
+        if old_blocks <= INDIRECT2_BOUND {
+            self.indirect3 = pool.next().unwrap();
+        }
+        let lo3 = old_blocks.clamp(INDIRECT2_BOUND, INDIRECT3_BOUND) - INDIRECT2_BOUND;
+        let hi3 = new_blocks_total.min(INDIRECT3_BOUND) - INDIRECT2_BOUND;
+        get_block_cache(self.indirect3 as usize, Arc::clone(block_device))
+            .lock()
+            .modify(0, |indirect3: &mut IndirectBlock| {
+                for i in lo3..hi3 {
+                    let (a, rem) = (i / INDIRECT2_COUNT, i % INDIRECT2_COUNT);
+                    let (b, c) = (rem / INDIRECT1_COUNT, rem % INDIRECT1_COUNT);
+                    if b == 0 && c == 0 {
+                        indirect3[a] = pool.next().unwrap();
+                    }
+                    get_block_cache(indirect3[a] as usize, Arc::clone(block_device))
+                        .lock()
+                        .modify(0, |indirect2: &mut IndirectBlock| {
+                            if c == 0 {
+                                indirect2[b] = pool.next().unwrap();
+                            }
+                            get_block_cache(indirect2[b] as usize, Arc::clone(block_device))
+                                .lock()
+                                .modify(0, |indirect1: &mut IndirectBlock| {
+                                    indirect1[c] = pool.next().unwrap();
+                                });
+                        });
+                }
+            });
+    }
+
+    /// Shrink this inode to empty, returning every data block id it had so
+    /// the caller can free them (and any now-unused index blocks).
+    pub fn clear_size(&mut self, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
+        let mut v: Vec<u32> = Vec::new();
+        let data_blocks = Self::data_blocks(self.size) as usize;
+        self.size = 0;
+
+        // direct
+        for i in 0..data_blocks.min(INODE_DIRECT_COUNT) {
+            v.push(self.direct[i]);
+            self.direct[i] = 0;
+        }
+        if data_blocks <= INODE_DIRECT_COUNT {
+            return v;
+        }
+
+        // indirect1
+        v.push(self.indirect1);
+        let hi1 = data_blocks.min(INDIRECT1_BOUND) - INODE_DIRECT_COUNT;
+        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
+            .lock()
+            .modify(0, |indirect1: &mut IndirectBlock| {
+                for entry in indirect1.iter().take(hi1) {
+                    v.push(*entry);
+                }
+            });
+        self.indirect1 = 0;
+        if data_blocks <= INDIRECT1_BOUND {
+            return v;
+        }
+
+        // indirect2
+        v.push(self.indirect2);
+        let hi2 = data_blocks.min(INDIRECT2_BOUND) - INDIRECT1_BOUND;
+        let full_a = hi2 / INDIRECT1_COUNT;
+        let rem_b = hi2 % INDIRECT1_COUNT;
+        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+            .lock()
+            .modify(0, |indirect2: &mut IndirectBlock| {
+                for entry in indirect2.iter().take(full_a) {
+                    v.push(*entry);
+                    get_block_cache(*entry as usize, Arc::clone(block_device))
+                        .lock()
+                        .modify(0, |indirect1: &mut IndirectBlock| {
+                            for block in indirect1.iter() {
+                                v.push(*block);
+                            }
+                        });
+                }
+                if rem_b > 0 {
+                    v.push(indirect2[full_a]);
+                    get_block_cache(indirect2[full_a] as usize, Arc::clone(block_device))
+                        .lock()
+                        .modify(0, |indirect1: &mut IndirectBlock| {
+                            for block in indirect1.iter().take(rem_b) {
+                                v.push(*block);
+                            }
+                        });
+                }
+            });
+        self.indirect2 = 0;
+        if data_blocks <= INDIRECT2_BOUND {
+            return v;
+        }
+
+        // indirect3
+        v.push(self.indirect3);
+        let hi3 = data_blocks.min(INDIRECT3_BOUND) - INDIRECT2_BOUND;
+        let full_a3 = hi3 / INDIRECT2_COUNT;
+        let rem3 = hi3 % INDIRECT2_COUNT;
+        let full_b3 = rem3 / INDIRECT1_COUNT;
+        let rem_c3 = rem3 % INDIRECT1_COUNT;
+        get_block_cache(self.indirect3 as usize, Arc::clone(block_device))
+            .lock()
+            .modify(0, |indirect3: &mut IndirectBlock| {
+                for entry in indirect3.iter().take(full_a3) {
+                    v.push(*entry);
+                    get_block_cache(*entry as usize, Arc::clone(block_device))
+                        .lock()
+                        .modify(0, |indirect2: &mut IndirectBlock| {
+                            for inner in indirect2.iter() {
+                                v.push(*inner);
+                                get_block_cache(*inner as usize, Arc::clone(block_device))
+                                    .lock()
+                                    .modify(0, |indirect1: &mut IndirectBlock| {
+                                        for block in indirect1.iter() {
+                                            v.push(*block);
+                                        }
+                                    });
+                            }
+                        });
+                }
+                if full_b3 > 0 || rem_c3 > 0 {
+                    v.push(indirect3[full_a3]);
+                    get_block_cache(indirect3[full_a3] as usize, Arc::clone(block_device))
+                        .lock()
+                        .modify(0, |indirect2: &mut IndirectBlock| {
+                            for entry in indirect2.iter().take(full_b3) {
+                                v.push(*entry);
+                                get_block_cache(*entry as usize, Arc::clone(block_device))
+                                    .lock()
+                                    .modify(0, |indirect1: &mut IndirectBlock| {
+                                        for block in indirect1.iter() {
+                                            v.push(*block);
+                                        }
+                                    });
+                            }
+                            if rem_c3 > 0 {
+                                v.push(indirect2[full_b3]);
+                                get_block_cache(indirect2[full_b3] as usize, Arc::clone(block_device))
+                                    .lock()
+                                    .modify(0, |indirect1: &mut IndirectBlock| {
+                                        for block in indirect1.iter().take(rem_c3) {
+                                            v.push(*block);
+                                        }
+                                    });
+                            }
+                        });
+                }
+            });
+        self.indirect3 = 0;
+        v
+    }
+
+    /// Read up to `buf.len()` bytes starting at `offset`, returning the
+    /// number of bytes actually read.
+    pub fn read_at(
+        &self,
+        offset: usize,
+        buf: &mut [u8],
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> usize {
+        let mut start = offset;
+        let end = (offset + buf.len()).min(self.size as usize);
+        if start >= end {
+            return 0;
+        }
+        let mut start_block = start / BLOCK_SZ;
+        let mut read_size = 0usize;
+        loop {
+            let mut end_current_block = (start / BLOCK_SZ + 1) * BLOCK_SZ;
+            end_current_block = end_current_block.min(end);
+            let block_read_size = end_current_block - start;
+            let dst = &mut buf[read_size..read_size + block_read_size];
+            get_block_cache(
+                self.get_block_id(start_block as u32, block_device) as usize,
+                Arc::clone(block_device),
+            )
+            .lock()
+            .read(0, |data_block: &DataBlock| {
+                let src = &data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_read_size];
+                dst.copy_from_slice(src);
+            });
+            read_size += block_read_size;
+            if end_current_block == end {
+                break;
+            }
+            start_block += 1;
+            start = end_current_block;
+        }
+        read_size
+    }
+
+    /// Write `buf` starting at `offset`. The caller must have already
+    /// grown the inode (via `increase_size`) to cover `offset + buf.len()`.
+    pub fn write_at(
+        &mut self,
+        offset: usize,
+        buf: &[u8],
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> usize {
+        let mut start = offset;
+        let end = (offset + buf.len()).min(self.size as usize);
+        assert!(start <= end);
+        let mut start_block = start / BLOCK_SZ;
+        let mut write_size = 0usize;
+        loop {
+            let mut end_current_block = (start / BLOCK_SZ + 1) * BLOCK_SZ;
+            end_current_block = end_current_block.min(end);
+            let block_write_size = end_current_block - start;
+            get_block_cache(
+                self.get_block_id(start_block as u32, block_device) as usize,
+                Arc::clone(block_device),
+            )
+            .lock()
+            .modify(0, |data_block: &mut DataBlock| {
+                let src = &buf[write_size..write_size + block_write_size];
+                let dst = &mut data_block[start % BLOCK_SZ..start % BLOCK_SZ + block_write_size];
+                dst.copy_from_slice(src);
+            });
+            write_size += block_write_size;
+            if end_current_block == end {
+                break;
+            }
+            start_block += 1;
+            start = end_current_block;
+        }
+        write_size
+    }
+}
+
+/// Size, in bytes, of one on-disk `DirEntry`.
+pub const DIRENT_SZ: usize = 32;
+
+/// One directory entry: a fixed-size name plus the inode id it refers to.
+#[repr(C)]
+pub struct DirEntry {
+    name: [u8; NAME_LENGTH_LIMIT + 1],
+    inode_id: u32,
+}
+
+impl DirEntry {
+    /// An empty (all-zero) directory entry, used as scratch space to read
+    /// into.
+    pub fn empty() -> Self {
+        Self {
+            name: [0u8; NAME_LENGTH_LIMIT + 1],
+            inode_id: 0,
+        }
+    }
+
+    /// Build a directory entry for `name` pointing at `inode_id`.
+    pub fn new(name: &str, inode_id: u32) -> Self {
+        let mut bytes = [0u8; NAME_LENGTH_LIMIT + 1];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        Self {
+            name: bytes,
+            inode_id,
+        }
+    }
+
+    /// View this entry as a byte slice, for reading via `DiskInode::read_at`.
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self as *const _ as usize as *const u8, DIRENT_SZ) }
+    }
+
+    /// View this entry as a mutable byte slice, for writing via
+    /// `DiskInode::write_at`.
+    pub fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self as *mut _ as usize as *mut u8, DIRENT_SZ) }
+    }
+
+    /// This entry's filename, with trailing NUL bytes trimmed.
+    pub fn name(&self) -> &str {
+        let len = (0usize..).find(|i| self.name[*i] == 0).unwrap();
+        core::str::from_utf8(&self.name[..len]).unwrap()
+    }
+
+    /// The inode id this entry refers to.
+    pub fn inode_id(&self) -> u32 {
+        self.inode_id
+    }
+}
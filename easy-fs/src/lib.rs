@@ -0,0 +1,25 @@
+#![no_std]
+#![deny(missing_docs)]
+#![allow(clippy::new_ret_no_self)]
+
+//! An easy file system isolated from the kernel
+extern crate alloc;
+
+mod bitmap;
+mod block_cache;
+mod block_dev;
+mod efs;
+mod layout;
+mod permission;
+mod vfs;
+
+/// The size, in bytes, of a block on the underlying block device.
+pub const BLOCK_SZ: usize = 512;
+use bitmap::Bitmap;
+use layout::{DiskInode, DiskInodeType, SuperBlock, NAME_LENGTH_LIMIT};
+pub use block_cache::{block_cache_sync_all, get_block_cache, BlockCache, BLOCK_CACHE_SIZE};
+pub use block_dev::BlockDevice;
+pub use efs::{EasyFileSystem, FsStat};
+pub use layout::{DirEntry, DIRENT_SZ};
+pub use permission::{check_access, PERM_R, PERM_W, PERM_X};
+pub use vfs::{find_by_path, split_parent, Inode};
@@ -0,0 +1,30 @@
+//! POSIX-style rwx permission checks over a `DiskInode`'s `mode` bits.
+
+/// Read permission bit within a single rwx triad.
+pub const PERM_R: u8 = 0b100;
+/// Write permission bit within a single rwx triad.
+pub const PERM_W: u8 = 0b010;
+/// Execute permission bit within a single rwx triad.
+pub const PERM_X: u8 = 0b001;
+
+/// Check whether a requester identified by `(uid, gid)` may access a file
+/// owned by `(file_uid, file_gid)` with permission bits `file_mode` (the
+/// low 9 bits, as three rwx triads: owner/group/other), for every bit set
+/// in `want`.
+///
+/// Root (`uid == 0`) is always allowed. Otherwise the owner triad applies
+/// when `uid == file_uid`, the group triad when `gid == file_gid`, and the
+/// "other" triad otherwise.
+pub fn check_access(uid: u32, gid: u32, file_uid: u32, file_gid: u32, file_mode: u16, want: u8) -> bool {
+    if uid == 0 {
+        return true;
+    }
+    let triad = if uid == file_uid {
+        (file_mode >> 6) & 0b111
+    } else if gid == file_gid {
+        (file_mode >> 3) & 0b111
+    } else {
+        file_mode & 0b111
+    } as u8;
+    triad & want == want
+}
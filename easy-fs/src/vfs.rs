@@ -1,6 +1,6 @@
 use super::{
     block_cache_sync_all, get_block_cache, BlockDevice, DirEntry, DiskInode, DiskInodeType,
-    EasyFileSystem, DIRENT_SZ,
+    EasyFileSystem, FsStat, DIRENT_SZ,
 };
 use alloc::string::String;
 use alloc::sync::Arc;
@@ -32,6 +32,11 @@ impl Inode {
             inode_id: Arc::new(Mutex::new(180)),
         }
     }
+    /// Overwrite the cached inode id (used once by callers who construct an
+    /// `Inode` directly, e.g. [`EasyFileSystem::root_inode`])
+    pub(crate) fn set_inode_id(&self, inode_id: u32) {
+        *self.inode_id.lock() = inode_id as u64;
+    }
     /// Call a function over a disk inode to read it
     fn read_disk_inode<V>(&self, f: impl FnOnce(&DiskInode) -> V) -> V {
         get_block_cache(self.block_id, Arc::clone(&self.block_device))
@@ -105,35 +110,18 @@ impl Inode {
         disk_inode.increase_size(new_size, v, &self.block_device);
     }
 
-    ///返回硬连接数(只能root_inode使用)
-    pub fn get_links(&self, inode_id: u32) -> u32 {
-        log::debug!("into get_links");
+    /// 返回硬连接数：直接读取 inode 自身维护的计数器，而不是每次都扫描目录
+    pub fn get_links(&self) -> u32 {
         let mut nlink = 0;
-        self.read_disk_inode(|root_disk_inode| {
-            let file_count = (root_disk_inode.size as usize) / DIRENT_SZ;
-            let mut dirent = DirEntry::empty();
-            for i in 0..file_count {
-                log::debug!("i is {}", i);
-                assert_eq!(
-                    root_disk_inode.read_at(
-                        DIRENT_SZ * i,
-                        dirent.as_bytes_mut(),
-                        &self.block_device,
-                    ),
-                    DIRENT_SZ,
-                );
-                if dirent.inode_id() == inode_id {
-                    nlink += 1;
-                }
-            }
-            log::debug!("exit loop");
+        self.read_disk_inode(|disk_node| {
+            nlink = disk_node.nlink;
         });
-        log::debug!("exit read_disk_inode");
         nlink
     }
 
-    /// 返回元数据
-    pub fn get_metadata(&self) -> (u64, u32) {
+    /// 返回元数据: (ino, mode, uid, gid, nlink)，mode 中已经包含文件类型位，
+    /// nlink 直接来自 inode 自身的计数器
+    pub fn get_metadata(&self) -> (u64, u32, u32, u32, u32) {
         log::debug!("into get_metadata");
         log::debug!(
             "block id is {}, offset is {}",
@@ -141,24 +129,58 @@ impl Inode {
             self.block_offset
         );
         let mut mode: u32 = 0;
+        let mut uid: u32 = 0;
+        let mut gid: u32 = 0;
+        let mut nlink: u32 = 0;
         self.read_disk_inode(|disk_node| {
-            if disk_node.is_dir() {
-                mode = 0o040000;
+            mode = if disk_node.is_dir() {
+                0o040000
             } else {
-                mode = 0o100000;
-            }
+                0o100000
+            } | disk_node.mode as u32;
+            uid = disk_node.uid;
+            gid = disk_node.gid;
+            nlink = disk_node.nlink;
         });
         let inode_id = *self.inode_id.lock();
         log::debug!("metadata indoe id is {}", inode_id);
-        (inode_id, mode)
+        (inode_id, mode, uid, gid, nlink)
+    }
+    /// The usage statistics of the filesystem this inode lives on. Every
+    /// inode shares the same underlying `EasyFileSystem`, so this is just a
+    /// convenience for reaching it without the caller holding a direct
+    /// reference to the `Arc<Mutex<EasyFileSystem>>`.
+    pub fn fs_stat(&self) -> FsStat {
+        self.fs.lock().stat()
+    }
+    /// 返回时间戳: (atime, mtime, ctime)
+    pub fn get_times(&self) -> (u64, u64, u64) {
+        let mut times = (0, 0, 0);
+        self.read_disk_inode(|disk_node| {
+            times = (disk_node.atime, disk_node.mtime, disk_node.ctime);
+        });
+        times
+    }
+    /// 修改权限位 (chmod)
+    pub fn chmod(&self, mode: u16) {
+        self.modify_disk_inode(|disk_node| {
+            disk_node.mode = mode & 0o777;
+        });
+    }
+    /// 修改属主/属组 (chown)
+    pub fn chown(&self, uid: u32, gid: u32) {
+        self.modify_disk_inode(|disk_node| {
+            disk_node.uid = uid;
+            disk_node.gid = gid;
+        });
     }
     /// 添加硬连接
-    pub fn add_link(&self, old_name: &str, new_name: &str) -> isize {
+    pub fn add_link(&self, old_name: &str, new_name: &str, now: u64) -> isize {
         let mut fs = self.fs.lock();
-        self.modify_disk_inode(|root_disk_inode| {
-            // 只能根目录节点使用
+        let linked_inode_id = self.modify_disk_inode(|root_disk_inode| {
+            // self 必须是目录节点，但不再假定它就是根目录
             assert!(root_disk_inode.is_dir());
-            if let Some(old_inode_id) = self.find_inode_id(old_name, root_disk_inode) {
+            self.find_inode_id(old_name, root_disk_inode).map(|old_inode_id| {
                 let file_count = (root_disk_inode.size as usize) / DIRENT_SZ;
                 let new_size = (file_count + 1) * DIRENT_SZ;
                 // increase size
@@ -170,23 +192,37 @@ impl Inode {
                     dirent.as_bytes(),
                     &self.block_device,
                 );
-                0
-            } else {
-                -1
-            }
-        })
+                root_disk_inode.ctime = now;
+                old_inode_id
+            })
+        });
+        // bump the target inode's own link count (done after releasing the
+        // block cache lock held for `self` above, since the target may
+        // happen to share a block with `self` and re-locking would deadlock)
+        if let Some(old_inode_id) = linked_inode_id {
+            let (tgt_block_id, tgt_block_offset) = fs.get_disk_inode_pos(old_inode_id);
+            get_block_cache(tgt_block_id as usize, Arc::clone(&self.block_device))
+                .lock()
+                .modify(tgt_block_offset, |target: &mut DiskInode| {
+                    target.nlink += 1;
+                    target.ctime = now;
+                });
+            0
+        } else {
+            -1
+        }
     }
 
-    /// 移除硬连接
-    pub fn remove_link(&self, name: &str) -> isize {
+    /// 移除硬连接；当目标inode的nlink归零时回收其数据块和inode编号
+    pub fn remove_link(&self, name: &str, now: u64) -> isize {
         let fs = self.fs.lock();
         let mut deleted = false;
+        let mut removed_inode_id: Option<u32> = None;
         self.modify_disk_inode(|root_disk_inode| {
-            // 只能根目录节点使用
+            // self 必须是目录节点，但不再假定它就是根目录
             assert!(root_disk_inode.is_dir());
             let file_count = (root_disk_inode.size as usize) / DIRENT_SZ;
             let mut dirent = DirEntry::empty();
-            // let mut inode_id = 0;
             for i in 0..file_count {
                 assert_eq!(
                     root_disk_inode.read_at(
@@ -196,43 +232,48 @@ impl Inode {
                     ),
                     DIRENT_SZ,
                 );
-                let empty = DirEntry::empty();
                 if dirent.name() == name {
+                    let empty = DirEntry::empty();
                     root_disk_inode.write_at(i * DIRENT_SZ, empty.as_bytes(), &self.block_device);
-                    // inode_id = dirent.inode_id();
+                    removed_inode_id = Some(dirent.inode_id());
                     deleted = true;
                 }
             }
-            // 在根目录里遍历寻找对应inode_id，如果没找到则释放对应的inode
-            // for i in 0..file_count {
-            //     assert_eq!(
-            //         root_disk_inode.read_at(
-            //             DIRENT_SZ * i,
-            //             dirent.as_bytes_mut(),
-            //             &self.block_device,
-            //         ),
-            //         DIRENT_SZ,
-            //     );
-            //     if dirent.inode_id() == inode_id {
-            //         let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
-            //         let inode = Arc::new(Self::new(
-            //             block_id,
-            //             block_offset,
-            //             self.fs.clone(),
-            //             self.block_device.clone(),
-            //         ));
-            //         inode.clear();
-            //     }
-            // }
             if deleted {
-                0
-            } else {
-                -1
+                root_disk_inode.ctime = now;
             }
-        })
+        });
+        if !deleted {
+            return -1;
+        }
+        let inode_id = removed_inode_id.unwrap();
+        let (tgt_block_id, tgt_block_offset) = fs.get_disk_inode_pos(inode_id);
+        let nlink_after = get_block_cache(tgt_block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(tgt_block_offset, |target: &mut DiskInode| {
+                target.nlink -= 1;
+                target.ctime = now;
+                target.nlink
+            });
+        if nlink_after == 0 {
+            // drop our lock on `self.fs` before reclaiming the target: `clear`
+            // below takes its own lock, and `spin::Mutex` is not reentrant
+            drop(fs);
+            let target = Self::new(
+                tgt_block_id,
+                tgt_block_offset,
+                self.fs.clone(),
+                self.block_device.clone(),
+            );
+            target.clear(now);
+            self.fs.lock().dealloc_inode(inode_id);
+        }
+        0
     }
-    /// Create inode under current inode by name
-    pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
+    /// Create inode under current inode by name. `now` (from the kernel's
+    /// time source) stamps the new inode's atime/mtime/ctime and this
+    /// directory's mtime/ctime.
+    pub fn create(&self, name: &str, now: u64) -> Option<Arc<Inode>> {
         log::debug!("into create");
 
         let mut fs = self.fs.lock();
@@ -254,9 +295,11 @@ impl Inode {
         get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
             .lock()
             .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-                new_inode.initialize(DiskInodeType::File);
+                new_inode.initialize(DiskInodeType::File, now);
             });
         self.modify_disk_inode(|root_inode| {
+            root_inode.mtime = now;
+            root_inode.ctime = now;
             // append file in the dirent
             let file_count = (root_inode.size as usize) / DIRENT_SZ;
             let new_size = (file_count + 1) * DIRENT_SZ;
@@ -289,6 +332,63 @@ impl Inode {
         Some(Arc::new(new_inode))
         // release efs lock automatically by compiler
     }
+    /// Create a subdirectory under current inode by name, pre-populated
+    /// with `.` (itself) and `..` (its parent) dirents. `now` stamps
+    /// timestamps the same way [`Inode::create`] does.
+    pub fn create_dir(&self, name: &str, now: u64) -> Option<Arc<Inode>> {
+        let mut fs = self.fs.lock();
+        let op = |root_inode: &DiskInode| {
+            // assert it is a directory
+            assert!(root_inode.is_dir());
+            // has a file/dir with this name already been created?
+            self.find_inode_id(name, root_inode)
+        };
+        if self.read_disk_inode(op).is_some() {
+            return None;
+        }
+        // alloc an inode for the new directory
+        let new_inode_id = fs.alloc_inode();
+        let (new_inode_block_id, new_inode_block_offset) = fs.get_disk_inode_pos(new_inode_id);
+        get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
+            .lock()
+            .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
+                new_inode.initialize(DiskInodeType::Directory, now);
+            });
+        self.modify_disk_inode(|root_inode| {
+            // append the new directory in our own dirent list
+            let file_count = (root_inode.size as usize) / DIRENT_SZ;
+            let new_size = (file_count + 1) * DIRENT_SZ;
+            self.increase_size(new_size as u32, root_inode, &mut fs);
+            let dirent = DirEntry::new(name, new_inode_id);
+            root_inode.write_at(
+                file_count * DIRENT_SZ,
+                dirent.as_bytes(),
+                &self.block_device,
+            );
+            root_inode.mtime = now;
+            root_inode.ctime = now;
+        });
+
+        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
+        let new_inode = Self::new(
+            block_id,
+            block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+        );
+        new_inode.set_inode_id(new_inode_id);
+        // populate `.` and `..` so the new directory can be walked both ways
+        new_inode.modify_disk_inode(|new_disk_inode| {
+            new_inode.increase_size(2 * DIRENT_SZ as u32, new_disk_inode, &mut fs);
+            let dot = DirEntry::new(".", new_inode_id);
+            new_disk_inode.write_at(0, dot.as_bytes(), &new_inode.block_device);
+            let dotdot = DirEntry::new("..", *self.inode_id.lock() as u32);
+            new_disk_inode.write_at(DIRENT_SZ, dotdot.as_bytes(), &new_inode.block_device);
+        });
+
+        block_cache_sync_all();
+        Some(Arc::new(new_inode))
+    }
     /// List inodes under current inode
     pub fn ls(&self) -> Vec<String> {
         let _fs = self.fs.lock();
@@ -306,23 +406,29 @@ impl Inode {
             v
         })
     }
-    /// Read data from current inode
-    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+    /// Read data from current inode, stamping atime with `now`
+    pub fn read_at(&self, offset: usize, buf: &mut [u8], now: u64) -> usize {
         let _fs = self.fs.lock();
-        self.read_disk_inode(|disk_inode| disk_inode.read_at(offset, buf, &self.block_device))
+        self.modify_disk_inode(|disk_inode| {
+            disk_inode.atime = now;
+            disk_inode.read_at(offset, buf, &self.block_device)
+        })
     }
-    /// Write data to current inode
-    pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+    /// Write data to current inode, stamping mtime/ctime with `now`
+    pub fn write_at(&self, offset: usize, buf: &[u8], now: u64) -> usize {
         let mut fs = self.fs.lock();
         let size = self.modify_disk_inode(|disk_inode| {
             self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
-            disk_inode.write_at(offset, buf, &self.block_device)
+            let written = disk_inode.write_at(offset, buf, &self.block_device);
+            disk_inode.mtime = now;
+            disk_inode.ctime = now;
+            written
         });
         block_cache_sync_all();
         size
     }
-    /// Clear the data in current inode
-    pub fn clear(&self) {
+    /// Clear the data in current inode, stamping mtime/ctime with `now`
+    pub fn clear(&self, now: u64) {
         let mut fs = self.fs.lock();
         self.modify_disk_inode(|disk_inode| {
             let size = disk_inode.size;
@@ -331,7 +437,31 @@ impl Inode {
             for data_block in data_blocks_dealloc.into_iter() {
                 fs.dealloc_data(data_block);
             }
+            disk_inode.mtime = now;
+            disk_inode.ctime = now;
         });
         block_cache_sync_all();
     }
 }
+
+/// Resolve a `/`-separated path (e.g. `a/b/c`, absolute or relative; leading
+/// and repeated `/` are ignored) starting from `root`, walking one
+/// component at a time via [`Inode::find`]. Returns the terminal inode, or
+/// `None` if any component along the way does not exist.
+pub fn find_by_path(root: &Arc<Inode>, path: &str) -> Option<Arc<Inode>> {
+    let mut current = Arc::clone(root);
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        current = current.find(component)?;
+    }
+    Some(current)
+}
+
+/// Split a path into its parent directory path and final component, e.g.
+/// `"a/b/c"` -> `("a/b", "c")`. A path with no `/` has an empty parent,
+/// meaning "resolve relative to `root` itself".
+pub fn split_parent(path: &str) -> (&str, &str) {
+    match path.trim_end_matches('/').rsplit_once('/') {
+        Some((parent, name)) => (parent, name),
+        None => ("", path),
+    }
+}
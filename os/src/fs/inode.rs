@@ -0,0 +1,318 @@
+//! The `easy-fs`-backed half of the filesystem: path resolution and the
+//! open file descriptors `sys_open` hands back.
+
+use super::File;
+use crate::drivers::BLOCK_DEVICE;
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::task::current_uid_gid;
+use crate::timer::get_time_us;
+use alloc::sync::Arc;
+use bitflags::bitflags;
+use easy_fs::{check_access, find_by_path, split_parent, EasyFileSystem, Inode, PERM_R, PERM_W};
+use lazy_static::*;
+
+lazy_static! {
+    static ref ROOT_INODE: Arc<Inode> = {
+        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
+        Arc::new(EasyFileSystem::root_inode(&efs))
+    };
+}
+
+/// A file opened through `sys_open`: an `easy-fs` inode plus the read/write
+/// permissions and cursor position this particular fd was opened with.
+pub struct OSInode {
+    readable: bool,
+    writable: bool,
+    inner: UPSafeCell<OSInodeInner>,
+}
+
+struct OSInodeInner {
+    offset: usize,
+    inode: Arc<Inode>,
+}
+
+impl OSInode {
+    /// Wrap `inode` as a fd opened with the given `readable`/`writable`
+    /// flags, cursor at the start.
+    pub fn new(readable: bool, writable: bool, inode: Arc<Inode>) -> Self {
+        Self {
+            readable,
+            writable,
+            inner: unsafe { UPSafeCell::new(OSInodeInner { offset: 0, inode }) },
+        }
+    }
+    /// This fd's underlying `easy-fs` inode.
+    pub fn inode(&self) -> Arc<Inode> {
+        Arc::clone(&self.inner.exclusive_access().inode)
+    }
+}
+
+/// Check the calling task's `(uid, gid)` against `inode`'s owner/mode for
+/// every bit set in `want`.
+fn check_current_access(inode: &Inode, want: u8) -> bool {
+    let (uid, gid) = current_uid_gid();
+    let (_ino, mode, file_uid, file_gid, _nlink) = inode.get_metadata();
+    check_access(uid, gid, file_uid, file_gid, mode as u16, want)
+}
+
+/// The timestamp handed to `easy-fs` for atime/mtime/ctime: microseconds
+/// since boot.
+fn now() -> u64 {
+    get_time_us() as u64
+}
+
+impl File for OSInode {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn check_access(&self, write: bool) -> bool {
+        let inner = self.inner.exclusive_access();
+        check_current_access(&inner.inode, if write { PERM_W } else { PERM_R })
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        let mut inner = self.inner.exclusive_access();
+        let mut total_read_size = 0usize;
+        for slice in buf.buffers.iter_mut() {
+            let read_size = inner.inode.read_at(inner.offset, slice, now());
+            if read_size == 0 {
+                break;
+            }
+            inner.offset += read_size;
+            total_read_size += read_size;
+        }
+        total_read_size
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        let mut inner = self.inner.exclusive_access();
+        let mut total_write_size = 0usize;
+        for slice in buf.buffers.iter() {
+            let write_size = inner.inode.write_at(inner.offset, slice, now());
+            assert_eq!(write_size, slice.len());
+            inner.offset += write_size;
+            total_write_size += write_size;
+        }
+        total_write_size
+    }
+    fn get_metadata(&self) -> (u64, u32, u32, u32, u32) {
+        self.inner.exclusive_access().inode.get_metadata()
+    }
+    fn get_times(&self) -> (u64, u64, u64) {
+        self.inner.exclusive_access().inode.get_times()
+    }
+}
+
+bitflags! {
+    /// Open-mode flags for `sys_open`, matching the Linux `O_*` bit layout
+    /// this kernel cares about.
+    pub struct OpenFlags: u32 {
+        /// Open for reading only.
+        const RDONLY = 0;
+        /// Open for writing only.
+        const WRONLY = 1 << 0;
+        /// Open for both reading and writing.
+        const RDWR = 1 << 1;
+        /// Create the file if it doesn't already exist.
+        const CREATE = 1 << 9;
+        /// Truncate an existing file to zero length on open.
+        const TRUNC = 1 << 10;
+    }
+}
+
+impl OpenFlags {
+    /// `(readable, writable)` implied by these flags.
+    pub fn read_write(&self) -> (bool, bool) {
+        if self.is_empty() {
+            (true, false)
+        } else if self.contains(Self::WRONLY) {
+            (false, true)
+        } else {
+            (true, true)
+        }
+    }
+}
+
+/// Resolve `path` (absolute or relative to the filesystem root, `/`-separated)
+/// and open it per `flags`, creating it first if `OpenFlags::CREATE` is set
+/// and it doesn't already exist. Fails if the calling task's `(uid, gid)`
+/// lacks the permission bits `flags` asks for on an existing file (a freshly
+/// created file is always owned by the caller, so there is nothing to check).
+pub fn open_file(path: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
+    let (readable, writable) = flags.read_write();
+    if flags.contains(OpenFlags::CREATE) {
+        if let Some(inode) = find_by_path(&ROOT_INODE, path) {
+            if !check_open_access(&inode, readable, writable) {
+                return None;
+            }
+            inode.clear(now());
+            Some(Arc::new(OSInode::new(readable, writable, inode)))
+        } else {
+            let (parent_path, name) = split_parent(path);
+            let parent = find_by_path(&ROOT_INODE, parent_path)?;
+            parent.create(name, now()).map(|inode| {
+                let (uid, gid) = current_uid_gid();
+                inode.chown(uid, gid);
+                Arc::new(OSInode::new(readable, writable, inode))
+            })
+        }
+    } else {
+        let inode = find_by_path(&ROOT_INODE, path)?;
+        if !check_open_access(&inode, readable, writable) {
+            return None;
+        }
+        if flags.contains(OpenFlags::TRUNC) {
+            inode.clear(now());
+        }
+        Some(Arc::new(OSInode::new(readable, writable, inode)))
+    }
+}
+
+/// Whether the calling task may open `inode` the way `readable`/`writable`
+/// ask for.
+fn check_open_access(inode: &Inode, readable: bool, writable: bool) -> bool {
+    (!readable || check_current_access(inode, PERM_R)) && (!writable || check_current_access(inode, PERM_W))
+}
+
+/// Create the directory at `path`: resolve its parent and create its final
+/// component as a subdirectory under it.
+pub fn mkdir(path: &str) -> isize {
+    let (parent_path, name) = split_parent(path);
+    match find_by_path(&ROOT_INODE, parent_path) {
+        Some(parent) => match parent.create_dir(name, now()) {
+            Some(_) => 0,
+            None => -1,
+        },
+        None => -1,
+    }
+}
+
+/// Create a hard link `new_path` -> `old_path`. Both must resolve to the
+/// same parent directory; there is no cross-directory variant of
+/// `Inode::add_link`.
+pub fn linkat(old_path: &str, new_path: &str) -> isize {
+    let (old_parent_path, old_name) = split_parent(old_path);
+    let (new_parent_path, new_name) = split_parent(new_path);
+    if old_parent_path != new_parent_path {
+        return -1;
+    }
+    match find_by_path(&ROOT_INODE, old_parent_path) {
+        Some(dir) => dir.add_link(old_name, new_name, now()),
+        None => -1,
+    }
+}
+
+/// Remove the link `path`, reclaiming the target inode once its link count
+/// reaches zero.
+pub fn unlinkat(path: &str) -> isize {
+    let (parent_path, name) = split_parent(path);
+    match find_by_path(&ROOT_INODE, parent_path) {
+        Some(dir) => dir.remove_link(name, now()),
+        None => -1,
+    }
+}
+
+/// Change `path`'s permission bits. Only the owner (or root) may do this.
+pub fn chmod(path: &str, mode: u16) -> isize {
+    match find_by_path(&ROOT_INODE, path) {
+        Some(inode) => {
+            let (uid, _gid) = current_uid_gid();
+            let (_ino, _mode, file_uid, _file_gid, _nlink) = inode.get_metadata();
+            if uid != 0 && uid != file_uid {
+                return -1;
+            }
+            inode.chmod(mode);
+            0
+        }
+        None => -1,
+    }
+}
+
+/// Change `path`'s owning uid/gid. Only root may do this.
+pub fn chown(path: &str, uid: u32, gid: u32) -> isize {
+    if current_uid_gid().0 != 0 {
+        return -1;
+    }
+    match find_by_path(&ROOT_INODE, path) {
+        Some(inode) => {
+            inode.chown(uid, gid);
+            0
+        }
+        None => -1,
+    }
+}
+
+#[repr(C)]
+/// Layout `sys_fstat` writes into userspace: identity, ownership,
+/// permission, link count, and timestamp metadata for one inode.
+pub struct Stat {
+    /// Inode number
+    pub ino: u64,
+    /// File type and permission bits (as `st_mode`)
+    pub mode: u32,
+    /// Owning user id
+    pub uid: u32,
+    /// Owning group id
+    pub gid: u32,
+    /// Number of hard links
+    pub nlink: u32,
+    /// Last access time
+    pub atime: u64,
+    /// Last modification time
+    pub mtime: u64,
+    /// Last status change time
+    pub ctime: u64,
+}
+
+#[repr(C)]
+/// Layout `sys_statfs` writes into userspace: filesystem-wide usage
+/// statistics, mirroring [`easy_fs::FsStat`].
+pub struct Statfs {
+    /// Size, in bytes, of a single block
+    pub block_size: u32,
+    /// Total number of blocks available to the data area
+    pub total_blocks: u32,
+    /// Number of data blocks not currently allocated
+    pub free_blocks: u32,
+    /// Total number of inodes the filesystem can hold
+    pub total_inodes: u32,
+    /// Number of inodes not currently allocated
+    pub free_inodes: u32,
+    /// Maximum length, in bytes, of a single filename component
+    pub name_max: u32,
+}
+
+/// Fill in `path`'s filesystem's usage statistics. `path` only needs to
+/// resolve to some inode; easy-fs supports exactly one mounted
+/// filesystem, so every path on it shares the same stats.
+pub fn statfs(path: &str) -> Option<Statfs> {
+    let inode = find_by_path(&ROOT_INODE, path)?;
+    let stat = inode.fs_stat();
+    Some(Statfs {
+        block_size: stat.block_size,
+        total_blocks: stat.total_blocks,
+        free_blocks: stat.free_blocks,
+        total_inodes: stat.total_inodes,
+        free_inodes: stat.free_inodes,
+        name_max: stat.name_max,
+    })
+}
+
+impl Stat {
+    /// Build a `Stat` from an inode's metadata and timestamps.
+    #[allow(clippy::too_many_arguments)]
+    pub fn init(ino: u64, mode: u32, uid: u32, gid: u32, nlink: u32, atime: u64, mtime: u64, ctime: u64) -> Self {
+        Self {
+            ino,
+            mode,
+            uid,
+            gid,
+            nlink,
+            atime,
+            mtime,
+            ctime,
+        }
+    }
+}
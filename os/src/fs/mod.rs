@@ -0,0 +1,42 @@
+//! File-like kernel objects: the common [`File`] trait every fd-table entry
+//! implements, plus the concrete kinds of thing a fd can point at.
+
+mod inode;
+mod stdio;
+
+use crate::mm::UserBuffer;
+pub use inode::{chmod, chown, linkat, mkdir, open_file, statfs, unlinkat, OSInode, OpenFlags, Stat, Statfs};
+pub use stdio::{Stdin, Stdout};
+
+/// Something that can sit in a task's fd table and be read/written as a
+/// byte stream from user space.
+pub trait File: Send + Sync {
+    /// Whether this fd was opened for reading.
+    fn readable(&self) -> bool;
+    /// Whether this fd was opened for writing.
+    fn writable(&self) -> bool;
+    /// Whether the calling task is still allowed to read (`write = false`) or
+    /// write (`write = true`) this fd right now. Checked separately from
+    /// `read`/`write` themselves so callers can reject a denied access with a
+    /// distinct error instead of the `0` those return for a clean EOF/no-op.
+    /// Kinds of file without owner/mode semantics (e.g. stdio) always allow it.
+    fn check_access(&self, _write: bool) -> bool {
+        true
+    }
+    /// Read into `buf`, returning the number of bytes actually read.
+    fn read(&self, buf: UserBuffer) -> usize;
+    /// Write from `buf`, returning the number of bytes actually written.
+    fn write(&self, buf: UserBuffer) -> usize;
+    /// `(ino, mode, uid, gid, nlink)` for `sys_fstat`. Character devices
+    /// like stdin/stdout have no backing inode, so this defaults to a
+    /// stable dummy identity owned by root rather than being a required
+    /// method.
+    fn get_metadata(&self) -> (u64, u32, u32, u32, u32) {
+        (0, 0o020666, 0, 0, 1)
+    }
+    /// `(atime, mtime, ctime)` for `sys_fstat`. Defaults to all-zero for
+    /// file kinds (like stdin/stdout) that don't track real timestamps.
+    fn get_times(&self) -> (u64, u64, u64) {
+        (0, 0, 0)
+    }
+}
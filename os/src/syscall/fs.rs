@@ -1,5 +1,5 @@
 //! File and filesystem-related syscalls
-use crate::fs::{linkat, open_file, unlinkat, OpenFlags, Stat};
+use crate::fs::{chmod, chown, linkat, mkdir, open_file, statfs, unlinkat, OpenFlags, Stat, Statfs};
 use crate::mm::{translated_byte_buffer, translated_str, UserBuffer};
 use crate::task::{current_task, current_user_token};
 
@@ -18,6 +18,9 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
         let file = file.clone();
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
+        if !file.check_access(true) {
+            return -1;
+        }
         file.write(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
     } else {
         -1
@@ -39,6 +42,9 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
         }
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
+        if !file.check_access(false) {
+            return -1;
+        }
         trace!("kernel: sys_read .. file.read");
         file.read(UserBuffer::new(translated_byte_buffer(token, buf, len))) as isize
     } else {
@@ -94,9 +100,10 @@ pub fn sys_fstat(_fd: usize, _st: *mut Stat) -> isize {
         let file = file.clone();
         // release current task TCB manually to avoid multi-borrow
         drop(inner);
-        let (ino, mode, nlink) = file.get_metadata();
+        let (ino, mode, uid, gid, nlink) = file.get_metadata();
+        let (atime, mtime, ctime) = file.get_times();
         log::debug!("get_metadata success in sys_fstat");
-        let temp = Stat::init(ino, mode, nlink);
+        let temp = Stat::init(ino, mode, uid, gid, nlink, atime, mtime, ctime);
         // 生成Stat的字节数组
         let temp_slice = unsafe {
             core::slice::from_raw_parts(
@@ -130,6 +137,17 @@ pub fn sys_fstat(_fd: usize, _st: *mut Stat) -> isize {
     }
 }
 
+/// Create the directory at `path`.
+pub fn sys_mkdir(_path: *const u8) -> isize {
+    trace!(
+        "kernel:pid[{}] sys_mkdir",
+        current_task().unwrap().pid.0
+    );
+    let token = current_user_token();
+    let path = translated_str(token, _path);
+    mkdir(path.as_str())
+}
+
 /// YOUR JOB: Implement linkat.
 pub fn sys_linkat(_old_name: *const u8, _new_name: *const u8) -> isize {
     trace!(
@@ -152,3 +170,37 @@ pub fn sys_unlinkat(_name: *const u8) -> isize {
     let name = translated_str(token, _name);
     unlinkat(name.as_str())
 }
+
+/// Change the permission bits of the file at `path`.
+pub fn sys_chmod(path: *const u8, mode: u32) -> isize {
+    trace!("kernel:pid[{}] sys_chmod", current_task().unwrap().pid.0);
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    chmod(path.as_str(), mode as u16)
+}
+
+/// Change the owning uid/gid of the file at `path`.
+pub fn sys_chown(path: *const u8, uid: u32, gid: u32) -> isize {
+    trace!("kernel:pid[{}] sys_chown", current_task().unwrap().pid.0);
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    chown(path.as_str(), uid, gid)
+}
+
+/// Report usage statistics for the filesystem `path` lives on.
+pub fn sys_statfs(path: *const u8, buf: *mut Statfs) -> isize {
+    trace!("kernel:pid[{}] sys_statfs", current_task().unwrap().pid.0);
+    let token = current_user_token();
+    let path = translated_str(token, path);
+    match statfs(path.as_str()) {
+        Some(stat) => {
+            let mut buffers = translated_byte_buffer(token, buf as *const u8, core::mem::size_of::<Statfs>());
+            let bytes = unsafe {
+                core::slice::from_raw_parts(&stat as *const _ as *const u8, core::mem::size_of::<Statfs>())
+            };
+            buffers[0].copy_from_slice(bytes);
+            0
+        }
+        None => -1,
+    }
+}
@@ -9,9 +9,9 @@ use crate::{
     },
     task::{
         add_task, current_task, current_user_token, exit_current_and_run_next,
-        suspend_current_and_run_next, TaskStatus, mmap, munmap
+        set_current_uid_gid, suspend_current_and_run_next, TaskStatus, mmap, munmap
     },
-    timer::{get_time_ms, get_time_us},
+    timer::get_time_us,
 };
 
 #[repr(C)]
@@ -181,10 +181,14 @@ pub fn sys_task_info(_ti: *mut TaskInfo) -> isize {
     // 使用 translated_byte_buffer 获取用户空间的缓冲区可变引用
     let buffers = translated_byte_buffer(token, _ti as *const u8, core::mem::size_of::<TaskInfo>());
 
+    // `user_time`/`kernel_time` (microseconds) are accumulated only while
+    // this task is actually scheduled, unlike `task_start_time`, which
+    // counts wall-clock time since the task was first scheduled and so
+    // overcounts once other tasks start sharing the CPU with it.
     let task_info = TaskInfo {
         status: TaskStatus::Running,
         syscall_times: inner.syscall_times,
-        time: get_time_ms() - inner.task_start_time,
+        time: (inner.user_time + inner.kernel_time) / 1000,
     };
     println!(
         "info.syscall_times[SYSCALL_GETTIMEOFDAY] = {}",
@@ -274,6 +278,14 @@ pub fn sys_spawn(_path: *const u8) -> isize {
 }
 
 
+/// Set the current task's `(uid, gid)`. Only root (`uid == 0`) may call this
+/// successfully; once a task has dropped to a non-root uid it can't regain
+/// root through this syscall.
+pub fn sys_setuid(uid: u32, gid: u32) -> isize {
+    trace!("kernel:pid[{}] sys_setuid", current_task().unwrap().pid.0);
+    set_current_uid_gid(uid, gid)
+}
+
 // YOUR JOB: Set task priority.
 pub fn sys_set_priority(_prio: isize) -> isize {
     trace!(
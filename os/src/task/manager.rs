@@ -0,0 +1,69 @@
+//! The global ready queue: every task that's `Ready` and waiting for the
+//! processor, ordered by whatever [`Scheduler`] policy is plugged in.
+
+use super::scheduler::new_scheduler;
+use super::{Scheduler, TaskControlBlock};
+use crate::sync::UPSafeCell;
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use lazy_static::*;
+
+/// Owns the run queue; all access goes through the module-level wrapper
+/// functions below so callers never touch the scheduler directly.
+pub struct TaskManager {
+    scheduler: Box<dyn Scheduler<Arc<TaskControlBlock>> + Send>,
+}
+
+impl TaskManager {
+    /// Build a task manager using the build's configured [`Scheduler`].
+    pub fn new() -> Self {
+        Self {
+            scheduler: new_scheduler(),
+        }
+    }
+    /// Make `task` eligible to be picked by a future [`TaskManager::fetch`].
+    pub fn add(&mut self, task: Arc<TaskControlBlock>) {
+        self.scheduler.insert(task);
+    }
+    /// Remove and return the next task to run, according to the configured
+    /// scheduling policy.
+    pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.scheduler.pop()
+    }
+    /// Update `task`'s scheduling priority.
+    pub fn set_priority(&mut self, task: &Arc<TaskControlBlock>, priority: usize) {
+        self.scheduler.set_priority(task, priority);
+    }
+    /// Drop `pid`'s per-item scheduler bookkeeping. Must be called once a
+    /// task exits for good (not merely suspended): pids are recycled, and
+    /// without this a new task handed a recycled pid could silently inherit
+    /// a dead task's leftover scheduling state.
+    pub fn forget(&mut self, pid: usize) {
+        self.scheduler.forget(pid);
+    }
+}
+
+lazy_static! {
+    /// The global run queue.
+    pub static ref TASK_MANAGER: UPSafeCell<TaskManager> = unsafe { UPSafeCell::new(TaskManager::new()) };
+}
+
+/// Add `task` to the ready queue.
+pub fn add_task(task: Arc<TaskControlBlock>) {
+    TASK_MANAGER.exclusive_access().add(task);
+}
+
+/// Pop the next task the scheduler policy picks.
+pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
+    TASK_MANAGER.exclusive_access().fetch()
+}
+
+/// Update `task`'s scheduling priority in the global run queue.
+pub fn set_task_priority(task: &Arc<TaskControlBlock>, priority: usize) {
+    TASK_MANAGER.exclusive_access().set_priority(task, priority);
+}
+
+/// Drop `pid`'s priority/stride bookkeeping now that it has exited for good.
+pub fn forget_task(pid: usize) {
+    TASK_MANAGER.exclusive_access().forget(pid);
+}
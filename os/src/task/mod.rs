@@ -3,324 +3,323 @@
 //! Everything about task management, like starting and switching tasks is
 //! implemented here.
 //!
-//! A single global instance of [`TaskManager`] called `TASK_MANAGER` controls
-//! all the tasks in the operating system.
+//! A task's lifecycle runs through [`TaskControlBlock`]: [`manager`] holds
+//! every `Ready` task in its run queue, [`processor`] tracks whichever one
+//! is actually running on this hart, and [`pid`] hands out the pid/kernel
+//! stack each one needs.
 //!
 //! Be careful when you see `__switch` ASM function in `switch.S`. Control flow around this function
 //! might not be what you expect.
 
 mod context;
+mod manager;
+mod pid;
+mod processor;
+mod scheduler;
 mod switch;
 #[allow(clippy::module_inception)]
 mod task;
 
-use crate::config::MAX_SYSCALL_NUM;
-use crate::loader::{get_app_data, get_num_app};
+use crate::loader::get_app_data_by_name;
 use crate::mm::{self, MapPermission, VirtPageNum};
-use crate::sync::UPSafeCell;
-use crate::timer::get_time_ms;
-use crate::trap::TrapContext;
-use alloc::vec::Vec;
+use crate::timer::get_time_us;
+use alloc::sync::Arc;
 use lazy_static::*;
-use switch::__switch;
-pub use task::{TaskControlBlock, TaskStatus};
+use manager::{add_task, forget_task};
+pub use task::{MmapArea, TaskControlBlock, TaskControlBlockInner, TaskStatus};
 
 pub use context::TaskContext;
+pub use manager::{fetch_task, set_task_priority};
+pub use processor::{
+    current_task, current_trap_cx, current_user_token, run_tasks, schedule, take_current_task,
+};
+pub use scheduler::{Scheduler, SchedKey};
 
-/// The task manager, where all the tasks are managed.
-///
-/// Functions implemented on `TaskManager` deals with all task state transitions
-/// and task context switching. For convenience, you can find wrappers around it
-/// in the module level.
-///
-/// Most of `TaskManager` are hidden behind the field `inner`, to defer
-/// borrowing checks to runtime. You can see examples on how to use `inner` in
-/// existing functions on `TaskManager`.
-pub struct TaskManager {
-    /// total number of tasks
-    num_app: usize,
-    /// use inner value to get mutable access
-    inner: UPSafeCell<TaskManagerInner>,
+lazy_static! {
+    /// The first task, parent of every orphaned task. Unlike every other
+    /// task it is never forked/spawned by another task; it comes straight
+    /// from the loader's "initproc" app and is added to the run queue by
+    /// [`add_initproc`].
+    pub static ref INITPROC: Arc<TaskControlBlock> = Arc::new(TaskControlBlock::new(
+        get_app_data_by_name("initproc").unwrap()
+    ));
 }
 
-/// The task manager inner in 'UPSafeCell'
-struct TaskManagerInner {
-    /// task list
-    tasks: Vec<TaskControlBlock>,
-    /// id of current `Running` task
-    current_task: usize,
+/// Add the initial task to the run queue. Called once during kernel init.
+pub fn add_initproc() {
+    add_task(INITPROC.clone());
 }
 
-lazy_static! {
-    /// a `TaskManager` global instance through lazy_static!
-    pub static ref TASK_MANAGER: TaskManager = {
-        println!("init TASK_MANAGER");
-        let num_app = get_num_app();
-        println!("num_app = {}", num_app);
-        let mut tasks: Vec<TaskControlBlock> = Vec::new();
-        for i in 0..num_app {
-            tasks.push(TaskControlBlock::new(get_app_data(i), i));
-        }
-        TaskManager {
-            num_app,
-            inner: unsafe {
-                UPSafeCell::new(TaskManagerInner {
-                    tasks,
-                    current_task: 0,
-                })
-            },
-        }
-    };
+/// Suspend the current 'Running' task and run the next task in task list.
+pub fn suspend_current_and_run_next() {
+    let task = take_current_task().unwrap();
+    let mut task_inner = task.inner_exclusive_access();
+    let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
+    task_inner.task_status = TaskStatus::Ready;
+    // Flush whatever's accrued since the last checkpoint into kernel_time
+    // now, while we still hold the outgoing task: once it's back in the
+    // run queue `schedule` no longer has a "current task" to charge it to.
+    let now = get_time_us();
+    task_inner.kernel_time += now - task_inner.last_checkpoint;
+    task_inner.last_checkpoint = now;
+    drop(task_inner);
+    add_task(task);
+    schedule(task_cx_ptr);
 }
 
-impl TaskManager {
-    /// 增加当前任务对应系统调用次数
-    fn increase_current_syscall(&self, syscall_id: usize) {
-        let mut inner = self.inner.exclusive_access();
-        let current_id = inner.current_task;
-        let current_task = &mut inner.tasks[current_id];
-        current_task.syscall_times[syscall_id] += 1;
+/// Exit the current 'Running' task and run the next task in task list,
+/// reporting `exit_code` to whichever `waitpid` eventually reaps it.
+pub fn exit_current_and_run_next(exit_code: i32) {
+    let task = take_current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.task_status = TaskStatus::Zombie;
+    inner.exit_code = exit_code;
+    // Flush the final segment of kernel time for the same reason
+    // `suspend_current_and_run_next` does: `schedule` won't see this task
+    // as current anymore.
+    let now = get_time_us();
+    inner.kernel_time += now - inner.last_checkpoint;
+    inner.last_checkpoint = now;
+    // Every child of the exiting task is orphaned onto `INITPROC` rather
+    // than left parentless, so it can still be waited on by someone.
+    {
+        let mut initproc_inner = INITPROC.inner_exclusive_access();
+        for child in inner.children.iter() {
+            child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
+            initproc_inner.children.push(Arc::clone(child));
+        }
     }
+    inner.children.clear();
+    inner.fd_table.clear();
+    drop(inner);
+    // This task is leaving the run queue for good: drop its priority/stride
+    // bookkeeping so a future task handed this (recycled) pid doesn't
+    // silently inherit it.
+    forget_task(task.getpid());
+    drop(task);
+    let mut _unused = TaskContext::zero_init();
+    schedule(&mut _unused as *mut _);
+}
 
-    ///返回当前任务的系统调用次数统计数组
-    fn get_task_syscall_times(&self) -> [u32; MAX_SYSCALL_NUM] {
-        let inner = self.inner.exclusive_access();
-        let current_id = inner.current_task;
-        inner.tasks[current_id].syscall_times
-    }
+/// Record that the current task just trapped from user mode into the
+/// kernel, crediting the elapsed time to `user_time`.
+pub fn record_user_to_kernel() {
+    let now = get_time_us();
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.user_time += now - inner.last_checkpoint;
+    inner.last_checkpoint = now;
+}
 
-    ///返回当前任务的开始时间
-    fn get_task_start_time(&self) -> usize {
-        let inner = self.inner.exclusive_access();
-        let current_id = inner.current_task;
-        inner.tasks[current_id].task_start_time
-    }
+/// Record that the current task is about to return from the kernel into
+/// user mode, crediting the elapsed time to `kernel_time`.
+pub fn record_kernel_to_user() {
+    let now = get_time_us();
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.kernel_time += now - inner.last_checkpoint;
+    inner.last_checkpoint = now;
+}
 
-    /// Run the first task in task list.
-    ///
-    /// Generally, the first task in task list is an idle task (we call it zero process later).
-    /// But in ch4, we load apps statically, so the first task is a real app.
-    fn run_first_task(&self) -> ! {
-        let mut inner = self.inner.exclusive_access();
-        let next_task = &mut inner.tasks[0];
-        next_task.task_status = TaskStatus::Running;
-        let next_task_cx_ptr = &next_task.task_cx as *const TaskContext;
-        // 记录第一个任务启动的时间
-        next_task.task_start_time = get_time_ms();
-        drop(inner);
-        let mut _unused = TaskContext::zero_init();
-        // before this, we should drop local variables that must be dropped manually
-        unsafe {
-            __switch(&mut _unused as *mut _, next_task_cx_ptr);
-        }
-        panic!("unreachable in run_first_task!");
-    }
+/// The current task's total CPU time (user + kernel) in microseconds.
+pub fn current_task_cpu_time_us() -> usize {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    inner.user_time + inner.kernel_time
+}
 
-    /// Change the status of current `Running` task into `Ready`.
-    fn mark_current_suspended(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let cur = inner.current_task;
-        inner.tasks[cur].task_status = TaskStatus::Ready;
-    }
+/// The current task's `(uid, gid)`, checked by [`easy_fs::check_access`]
+/// against a file's owner/mode before every open/read/write.
+pub fn current_uid_gid() -> (u32, u32) {
+    let task = current_task().unwrap();
+    let inner = task.inner_exclusive_access();
+    (inner.uid, inner.gid)
+}
 
-    /// Change the status of current `Running` task into `Exited`.
-    fn mark_current_exited(&self) {
-        let mut inner = self.inner.exclusive_access();
-        let cur = inner.current_task;
-        inner.tasks[cur].task_status = TaskStatus::Exited;
+/// Set the current task's `(uid, gid)`, following the usual `setuid`/`setgid`
+/// rule: only root (`uid == 0`) may change identity at all, since every task
+/// otherwise starts as and stays root and `check_access` would be unreachable
+/// dead code.
+pub fn set_current_uid_gid(uid: u32, gid: u32) -> isize {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    if inner.uid != 0 {
+        return -1;
     }
+    inner.uid = uid;
+    inner.gid = gid;
+    0
+}
 
-    /// Find next task to run and return task id.
-    ///
-    /// In this case, we only return the first `Ready` task in task list.
-    fn find_next_task(&self) -> Option<usize> {
-        let inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        (current + 1..current + self.num_app + 1)
-            .map(|id| id % self.num_app)
-            .find(|id| inner.tasks[*id].task_status == TaskStatus::Ready)
-    }
+/// 增加当前任务对应系统调用的次数
+pub fn increase_current_syscall(syscall_id: usize) {
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+    inner.syscall_times[syscall_id] += 1;
+}
 
-    /// Get the current 'Running' task's token.
-    fn get_current_token(&self) -> usize {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.current_task].get_user_token()
-    }
+/// Change the current 'Running' task's program break
+pub fn change_program_brk(size: i32) -> Option<usize> {
+    current_task().unwrap().change_program_brk(size)
+}
 
-    /// Get the current 'Running' task's trap contexts.
-    fn get_current_trap_cx(&self) -> &'static mut TrapContext {
-        let inner = self.inner.exclusive_access();
-        inner.tasks[inner.current_task].get_trap_cx()
+/// 申请内存
+pub fn mmap(start: usize, len: usize, port: usize) -> isize {
+    // 检查起始地址是否页对齐和port的合法性（除低3位外其余全为0且低3位不能全为0）
+    if (start & 0xFFF) != 0 || port & !0x7 != 0 || port & 0x7 == 0 {
+        println!("invaild address or port");
+        return -1;
     }
 
-    /// Change the current 'Running' task's program break
-    pub fn change_current_program_brk(&self, size: i32) -> Option<usize> {
-        let mut inner = self.inner.exclusive_access();
-        let cur = inner.current_task;
-        inner.tasks[cur].change_program_brk(size)
-    }
+    let start_va = mm::VirtAddr::from(start);
+    let end_va = mm::VirtAddr::from(start + len);
+    let start_vpn: VirtPageNum = start_va.floor();
+    let end_vpn: VirtPageNum = end_va.ceil();
 
-    /// Switch current `Running` task to the task we have found,
-    /// or there is no `Ready` task and we can exit with all applications completed
-    fn run_next_task(&self) {
-        if let Some(next) = self.find_next_task() {
-            let mut inner = self.inner.exclusive_access();
-            let current = inner.current_task;
-            // 记录任务第一次被调度的时间
-            if inner.tasks[current].task_start_time == 0 {
-                inner.tasks[current].task_start_time = get_time_ms();
-            }
-            inner.tasks[next].task_status = TaskStatus::Running;
-            inner.current_task = next;
-            let current_task_cx_ptr = &mut inner.tasks[current].task_cx as *mut TaskContext;
-            let next_task_cx_ptr = &inner.tasks[next].task_cx as *const TaskContext;
-            drop(inner);
-            // before this, we should drop local variables that must be dropped manually
-            unsafe {
-                __switch(current_task_cx_ptr, next_task_cx_ptr);
-            }
-            // go back to user mode
-        } else {
-            panic!("All applications completed!");
-        }
-    }
-    fn mmap(&self, start: usize, len: usize, port: usize) -> isize {
-        // 获取地址空间
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        let m_set = &mut inner.tasks[current].memory_set;
-
-        // 检查起始地址是否页对齐和port的合法性（除低3位外其余全为0且低3位不能全为0）
-        if (start & 0xFFF) != 0 || port & !0x7 != 0 || port & 0x7 == 0 {
-            println!("invaild address or port");
-            return -1;
-        }
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
 
-        // 检查地址范围内是否存在已经映射的页
-        let start_va = mm::VirtAddr::from(start);
-        let end_va = mm::VirtAddr::from(start + len);
-        let start_vpn: VirtPageNum = start_va.floor();
-        let end_vpn: VirtPageNum = end_va.ceil();
-
-        for vpn in mm::VPNRange::new(start_vpn, end_vpn) {
-            if let Some(pte) = m_set.translate(vpn) {
-                // 已经被映射过了
-                if pte.is_valid() {
-                    println!("address already mapped");
-                    return -1;
-                };
-            }
+    // O(log n) overlap check: only the immediate predecessor (from the
+    // left) and successor (from the right) of `start_vpn` can possibly
+    // overlap the new region, since existing regions never overlap.
+    if let Some((_, pred)) = inner.mmap_areas.range(..=start_vpn).next_back() {
+        if pred.end_vpn > start_vpn {
+            println!("address already mapped");
+            return -1;
         }
-
-        // 将port转换成MapPermission
-        // MapPermission是从第1位开始的，所以port要左移1位，还要注意U位置1
-        let flags = MapPermission::from_bits((port << 1) as u8).unwrap() | MapPermission::U;
-
-        // 以逻辑段为单位将该地址范围加入到应用的地址空间中
-        // 函数内部也是按页处理的
-        m_set.insert_framed_area(start_va, end_va, flags);
-
-        0
     }
-
-    /// 取消内存映射
-    fn munmap(&self, start: usize, len: usize) -> isize {
-        // 检查start是否对齐
-        if start & 0xFFF != 0 {
-            println!("invaild address or port");
+    if let Some((&succ_start, _)) = inner.mmap_areas.range(start_vpn..).next() {
+        if succ_start < end_vpn {
+            println!("address already mapped");
             return -1;
         }
-
-        // 获取地址空间
-        let mut inner = self.inner.exclusive_access();
-        let current = inner.current_task;
-        let m_set = &mut inner.tasks[current].memory_set;
-
-        // 检查地址范围内是否存在没被映射的页
-        let start_va = mm::VirtAddr::from(start);
-        let end_va = mm::VirtAddr::from(start + len);
-        let start_vpn: VirtPageNum = start_va.floor();
-        let end_vpn: VirtPageNum = end_va.ceil();
-
-        for vpn in mm::VPNRange::new(start_vpn, end_vpn) {
-            if let Some(pte) = m_set.translate(vpn) {
-                if !pte.is_valid() {
-                    println!("exists address not mapped");
-                    return -1;
-                };
-            }
-        }
-        m_set.del_framed_area(start_va, end_va);
-        0
     }
-}
-/// 申请内存
-pub fn mmap(start: usize, len: usize, port: usize) -> isize {
-    TASK_MANAGER.mmap(start, len, port)
-}
-/// 取消内存映射
-pub fn munmap(start: usize, len: usize) -> isize {
-    TASK_MANAGER.munmap(start, len)
-}
 
-/// 增加当前任务对应系统调用的次数
-pub fn increase_current_syscall(syscall_id: usize) {
-    TASK_MANAGER.increase_current_syscall(syscall_id);
-}
-///返回当前任务的系统调用次数统计数组
-pub fn get_task_syscall_times() -> [u32; MAX_SYSCALL_NUM] {
-    TASK_MANAGER.get_task_syscall_times()
-}
-
-///返回当前任务的开始时间
-pub fn get_task_start_time() -> usize {
-    TASK_MANAGER.get_task_start_time()
+    // 将port转换成MapPermission
+    // MapPermission是从第1位开始的，所以port要左移1位，还要注意U位置1
+    let flags = MapPermission::from_bits((port << 1) as u8).unwrap() | MapPermission::U;
+
+    // Lazy mapping: only record the pending region here. Frames are
+    // allocated one page at a time by `handle_lazy_page_fault` the
+    // first time each page is actually touched.
+    inner.mmap_areas.insert(
+        start_vpn,
+        MmapArea {
+            end_vpn,
+            permission: flags,
+            lazy: true,
+        },
+    );
+
+    0
 }
 
-/// Run the first task in task list.
-pub fn run_first_task() {
-    TASK_MANAGER.run_first_task();
+/// The kind of access that caused a page fault, so [`handle_lazy_page_fault`]
+/// can check it against the faulting region's permission bits instead of
+/// blindly satisfying every fault inside a lazy region.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FaultAccess {
+    Load,
+    Store,
+    Instruction,
 }
 
-/// Switch current `Running` task to the task we have found,
-/// or there is no `Ready` task and we can exit with all applications completed
-fn run_next_task() {
-    TASK_MANAGER.run_next_task();
-}
+/// Try to resolve a page fault at `fault_va` by allocating a frame for a
+/// lazily-mapped region that covers it, provided `access` is actually
+/// permitted by that region. Returns whether the fault was satisfied; a
+/// fault outside any known lazy region, or one whose access direction the
+/// region doesn't permit (e.g. a store against a read-only region), is not
+/// handled here and should still kill the task as a genuine protection
+/// violation.
+pub fn handle_lazy_page_fault(fault_va: usize, access: FaultAccess) -> bool {
+    let fault_vpn: VirtPageNum = mm::VirtAddr::from(fault_va).floor();
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
+
+    let permission = match inner
+        .mmap_areas
+        .range(..=fault_vpn)
+        .next_back()
+        .filter(|(_, area)| area.lazy && fault_vpn < area.end_vpn)
+    {
+        Some((_, area)) => area.permission,
+        None => return false,
+    };
+    let required = match access {
+        FaultAccess::Load => MapPermission::R,
+        FaultAccess::Store => MapPermission::W,
+        FaultAccess::Instruction => MapPermission::X,
+    };
+    if !permission.contains(required) {
+        return false;
+    }
 
-/// Change the status of current `Running` task into `Ready`.
-fn mark_current_suspended() {
-    TASK_MANAGER.mark_current_suspended();
+    let page_start = mm::VirtAddr::from(fault_vpn);
+    let page_end = mm::VirtAddr::from(VirtPageNum(fault_vpn.0 + 1));
+    inner.memory_set.insert_framed_area(page_start, page_end, permission);
+    true
 }
 
-/// Change the status of current `Running` task into `Exited`.
-fn mark_current_exited() {
-    TASK_MANAGER.mark_current_exited();
-}
+/// 取消内存映射
+pub fn munmap(start: usize, len: usize) -> isize {
+    // 检查start是否对齐
+    if start & 0xFFF != 0 {
+        println!("invaild address or port");
+        return -1;
+    }
 
-/// Suspend the current 'Running' task and run the next task in task list.
-pub fn suspend_current_and_run_next() {
-    mark_current_suspended();
-    run_next_task();
-}
+    let start_va = mm::VirtAddr::from(start);
+    let end_va = mm::VirtAddr::from(start + len);
+    let start_vpn: VirtPageNum = start_va.floor();
+    let end_vpn: VirtPageNum = end_va.ceil();
 
-/// Exit the current 'Running' task and run the next task in task list.
-pub fn exit_current_and_run_next() {
-    mark_current_exited();
-    run_next_task();
-}
+    let task = current_task().unwrap();
+    let mut inner = task.inner_exclusive_access();
 
-/// Get the current 'Running' task's token.
-pub fn current_user_token() -> usize {
-    TASK_MANAGER.get_current_token()
-}
+    // The requested range must fall entirely inside a single tracked
+    // region; find it via its predecessor by start VPN.
+    let found = inner.mmap_areas.range(..=start_vpn).next_back().and_then(|(&s, a)| {
+        if a.end_vpn >= end_vpn {
+            Some((s, a.end_vpn, a.permission, a.lazy))
+        } else {
+            None
+        }
+    });
+    let (area_start, area_end, permission, lazy) = match found {
+        Some(area) => area,
+        None => {
+            println!("exists address not mapped");
+            return -1;
+        }
+    };
 
-/// Get the current 'Running' task's trap contexts.
-pub fn current_trap_cx() -> &'static mut TrapContext {
-    TASK_MANAGER.get_current_trap_cx()
-}
+    inner.mmap_areas.remove(&area_start);
+    // Pages that were never touched (lazy and not yet faulted in) have
+    // no PTE to begin with; `del_framed_area` only tears down whatever
+    // is actually present in this range.
+    inner.memory_set.del_framed_area(start_va, end_va);
+
+    // Re-insert whatever survives on either side of the unmapped slice.
+    if area_start < start_vpn {
+        inner.mmap_areas.insert(
+            area_start,
+            MmapArea {
+                end_vpn: start_vpn,
+                permission,
+                lazy,
+            },
+        );
+    }
+    if end_vpn < area_end {
+        inner.mmap_areas.insert(
+            end_vpn,
+            MmapArea {
+                end_vpn: area_end,
+                permission,
+                lazy,
+            },
+        );
+    }
 
-/// Change the current 'Running' task's program break
-pub fn change_program_brk(size: i32) -> Option<usize> {
-    TASK_MANAGER.change_current_program_brk(size)
+    0
 }
@@ -0,0 +1,96 @@
+//! Pid allocation and the per-task kernel stack that lives below the
+//! trampoline page, indexed by pid rather than by a fixed app slot.
+
+use crate::config::kernel_stack_position;
+use crate::mm::{MapPermission, VirtAddr, KERNEL_SPACE};
+use crate::sync::UPSafeCell;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// Recycling pid allocator: hands out the most recently freed pid (from a
+/// previous [`PidHandle`] drop) if there is one, and only otherwise grows
+/// `current`.
+struct PidAllocator {
+    current: usize,
+    recycled: Vec<usize>,
+}
+
+impl PidAllocator {
+    pub fn new() -> Self {
+        PidAllocator {
+            current: 0,
+            recycled: Vec::new(),
+        }
+    }
+    pub fn alloc(&mut self) -> PidHandle {
+        if let Some(pid) = self.recycled.pop() {
+            PidHandle(pid)
+        } else {
+            self.current += 1;
+            PidHandle(self.current - 1)
+        }
+    }
+    pub fn dealloc(&mut self, pid: usize) {
+        assert!(pid < self.current);
+        assert!(
+            !self.recycled.iter().any(|ppid| *ppid == pid),
+            "pid {} has been deallocated!",
+            pid
+        );
+        self.recycled.push(pid);
+    }
+}
+
+lazy_static! {
+    static ref PID_ALLOCATOR: UPSafeCell<PidAllocator> =
+        unsafe { UPSafeCell::new(PidAllocator::new()) };
+}
+
+/// An allocated pid; returns it to [`PID_ALLOCATOR`] when dropped.
+pub struct PidHandle(pub usize);
+
+impl Drop for PidHandle {
+    fn drop(&mut self) {
+        PID_ALLOCATOR.exclusive_access().dealloc(self.0);
+    }
+}
+
+/// Allocate a fresh pid.
+pub fn pid_alloc() -> PidHandle {
+    PID_ALLOCATOR.exclusive_access().alloc()
+}
+
+/// A task's kernel-mode stack, mapped into kernel space at a pid-indexed
+/// offset below the trampoline page with an unmapped guard page beneath it.
+/// Unmapped again when dropped.
+pub struct KernelStack {
+    pid: usize,
+}
+
+impl KernelStack {
+    /// Map a fresh kernel stack for `pid_handle`.
+    pub fn new(pid_handle: &PidHandle) -> Self {
+        let pid = pid_handle.0;
+        let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(pid);
+        KERNEL_SPACE.exclusive_access().insert_framed_area(
+            VirtAddr::from(kernel_stack_bottom),
+            VirtAddr::from(kernel_stack_top),
+            MapPermission::R | MapPermission::W,
+        );
+        KernelStack { pid }
+    }
+    /// The current top of this kernel stack.
+    pub fn get_top(&self) -> usize {
+        let (_, kernel_stack_top) = kernel_stack_position(self.pid);
+        kernel_stack_top
+    }
+}
+
+impl Drop for KernelStack {
+    fn drop(&mut self) {
+        let (kernel_stack_bottom, kernel_stack_top) = kernel_stack_position(self.pid);
+        KERNEL_SPACE
+            .exclusive_access()
+            .del_framed_area(VirtAddr::from(kernel_stack_bottom), VirtAddr::from(kernel_stack_top));
+    }
+}
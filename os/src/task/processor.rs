@@ -0,0 +1,110 @@
+//! The processor: tracks which task (if any) is running on this hart right
+//! now, and carries the idle control flow that hands off to it and takes
+//! control back when it stops running.
+
+use super::context::TaskContext;
+use super::manager::fetch_task;
+use super::switch::__switch;
+use super::{TaskControlBlock, TaskStatus};
+use crate::sync::UPSafeCell;
+use crate::timer::{get_time_ms, get_time_us};
+use crate::trap::TrapContext;
+use alloc::sync::Arc;
+use lazy_static::*;
+
+/// Per-hart scheduling state: the task currently running, and the context
+/// to `__switch` back into once it stops (directly or via [`schedule`]).
+pub struct Processor {
+    current: Option<Arc<TaskControlBlock>>,
+    idle_task_cx: TaskContext,
+}
+
+impl Processor {
+    /// Construct a `Processor` with no task running yet.
+    pub fn new() -> Self {
+        Self {
+            current: None,
+            idle_task_cx: TaskContext::zero_init(),
+        }
+    }
+    fn get_idle_task_cx_ptr(&mut self) -> *mut TaskContext {
+        &mut self.idle_task_cx as *mut _
+    }
+    /// Take the running task out, leaving nothing in its place.
+    pub fn take_current(&mut self) -> Option<Arc<TaskControlBlock>> {
+        self.current.take()
+    }
+    /// Clone a handle to the task currently running, if any.
+    pub fn current(&self) -> Option<Arc<TaskControlBlock>> {
+        self.current.as_ref().map(Arc::clone)
+    }
+}
+
+lazy_static! {
+    /// The (single-hart) processor state.
+    pub static ref PROCESSOR: UPSafeCell<Processor> = unsafe { UPSafeCell::new(Processor::new()) };
+}
+
+/// Take the task currently running on this hart, if any.
+pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
+    PROCESSOR.exclusive_access().take_current()
+}
+
+/// Clone a handle to the task currently running on this hart.
+pub fn current_task() -> Option<Arc<TaskControlBlock>> {
+    PROCESSOR.exclusive_access().current()
+}
+
+/// The current task's address space token (`satp` value).
+pub fn current_user_token() -> usize {
+    current_task().unwrap().get_user_token()
+}
+
+/// The current task's trap context.
+pub fn current_trap_cx() -> &'static mut TrapContext {
+    current_task().unwrap().inner_exclusive_access().get_trap_cx()
+}
+
+/// The idle control flow: repeatedly fetch whatever the scheduler picks
+/// next and `__switch` into it, coming back here only once that task
+/// suspends, exits, or is otherwise re-scheduled away.
+pub fn run_tasks() -> ! {
+    loop {
+        let mut processor = PROCESSOR.exclusive_access();
+        if let Some(task) = fetch_task() {
+            let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
+            let mut task_inner = task.inner_exclusive_access();
+            let next_task_cx_ptr = &task_inner.task_cx as *const TaskContext;
+            task_inner.task_status = TaskStatus::Running;
+            // The segment of kernel time between now and the task's next
+            // trap starts here; it was last flushed when the task stopped
+            // running (suspend/exit) or, for a brand new task, never.
+            let now = get_time_us();
+            task_inner.last_checkpoint = now;
+            if task_inner.task_start_time == 0 {
+                task_inner.task_start_time = get_time_ms();
+            }
+            drop(task_inner);
+            processor.current = Some(task);
+            drop(processor);
+            unsafe {
+                __switch(idle_task_cx_ptr, next_task_cx_ptr);
+            }
+        }
+    }
+}
+
+/// `__switch` out of `switched_task_cx_ptr` and back into the idle loop in
+/// [`run_tasks`].
+///
+/// Every caller has already taken the outgoing task out of [`PROCESSOR`]
+/// (via [`take_current_task`]) by the time it calls this, so there is no
+/// "current task" left here to flush kernel time against; callers must
+/// account for the segment up to this point themselves, against the task
+/// reference they still hold, before calling in.
+pub fn schedule(switched_task_cx_ptr: *mut TaskContext) {
+    let idle_task_cx_ptr = PROCESSOR.exclusive_access().get_idle_task_cx_ptr();
+    unsafe {
+        __switch(switched_task_cx_ptr, idle_task_cx_ptr);
+    }
+}
@@ -0,0 +1,191 @@
+//! Pluggable run-queue scheduling policies.
+//!
+//! [`Scheduler`] abstracts over "which task is runnable next", decoupling
+//! that decision from [`super::manager::TaskManager`], which only ever talks
+//! to a `Box<dyn Scheduler<Arc<TaskControlBlock>>>`. `suspend_current_and_run_next`
+//! re-enqueues the current task via [`Scheduler::insert`], and
+//! `exit_current_and_run_next` simply drops it instead.
+//!
+//! Implementations key their own per-item bookkeeping (priority, stride) by
+//! [`SchedKey::sched_key`] rather than requiring `T: Eq`/`Ord`, since `T` is
+//! typically an `Arc<TaskControlBlock>` and tasks are identified by pid, not
+//! by comparing their contents.
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+
+/// A scheduling policy over a collection of runnable items.
+pub trait Scheduler<T> {
+    /// Make `item` eligible to be picked by a future `pop`.
+    fn insert(&mut self, item: T);
+    /// Look at the item `pop` would currently return, without removing it.
+    fn peek(&self) -> Option<&T>;
+    /// Remove and return the next item to run, according to this policy.
+    fn pop(&mut self) -> Option<T>;
+    /// Remove a specific item from contention, e.g. because it exited.
+    fn remove(&mut self, item: &T) -> Option<T>;
+    /// Update the scheduling priority of `item`. Policies without a notion
+    /// of priority (e.g. FIFO) ignore this.
+    fn set_priority(&mut self, item: &T, priority: usize) {
+        let _ = (item, priority);
+    }
+    /// Drop any per-item bookkeeping keyed by `key`, e.g. because the task it
+    /// identifies has exited for good. Unlike `remove`, this does not require
+    /// the item still be present in the ready queue, and policies without
+    /// per-item bookkeeping (e.g. FIFO) ignore it.
+    fn forget(&mut self, key: usize) {
+        let _ = key;
+    }
+}
+
+/// Extracts a stable identity key from a schedulable item, used by
+/// [`Scheduler`] implementations to key their own per-item bookkeeping
+/// without requiring `T` itself to implement `Eq`/`Ord`.
+pub trait SchedKey {
+    /// A value that uniquely identifies this item among every other
+    /// currently-known item (e.g. a task's pid).
+    fn sched_key(&self) -> usize;
+}
+
+impl SchedKey for usize {
+    fn sched_key(&self) -> usize {
+        *self
+    }
+}
+
+impl SchedKey for Arc<super::TaskControlBlock> {
+    fn sched_key(&self) -> usize {
+        self.getpid()
+    }
+}
+
+/// First-in-first-out scheduler: tasks run in the order they became ready.
+pub struct FifoScheduler<T> {
+    queue: VecDeque<T>,
+}
+
+impl<T> FifoScheduler<T> {
+    /// Create an empty FIFO scheduler.
+    pub fn new() -> Self {
+        Self {
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+impl<T: SchedKey> Scheduler<T> for FifoScheduler<T> {
+    fn insert(&mut self, item: T) {
+        self.queue.push_back(item);
+    }
+    fn peek(&self) -> Option<&T> {
+        self.queue.front()
+    }
+    fn pop(&mut self) -> Option<T> {
+        self.queue.pop_front()
+    }
+    fn remove(&mut self, item: &T) -> Option<T> {
+        let key = item.sched_key();
+        let pos = self.queue.iter().position(|x| x.sched_key() == key)?;
+        self.queue.remove(pos)
+    }
+}
+
+/// The "big stride" constant used by [`StrideScheduler`].
+///
+/// Every task's `pass` is `BIG_STRIDE / priority`; since `priority >= 2`, no
+/// single `pass` can exceed `BIG_STRIDE / 2`, which keeps the spread between
+/// any two live strides inside `BIG_STRIDE` and lets comparisons be done with
+/// wrapping arithmetic.
+pub const BIG_STRIDE: usize = 65535;
+/// Default priority assigned to a task the scheduler hasn't seen before.
+pub const DEFAULT_PRIORITY: usize = 16;
+
+/// Stride scheduler: always runs the ready task with the smallest stride,
+/// then advances that task's stride by its `pass = BIG_STRIDE / priority`.
+/// Priority and stride are keyed by [`SchedKey::sched_key`] rather than by
+/// `T` itself, so this works equally well over plain `usize` ids or over
+/// `Arc<TaskControlBlock>` handles.
+pub struct StrideScheduler<T> {
+    ready: VecDeque<T>,
+    priority: BTreeMap<usize, usize>,
+    stride: BTreeMap<usize, usize>,
+}
+
+impl<T> StrideScheduler<T> {
+    /// Create an empty stride scheduler.
+    pub fn new() -> Self {
+        Self {
+            ready: VecDeque::new(),
+            priority: BTreeMap::new(),
+            stride: BTreeMap::new(),
+        }
+    }
+
+    fn pass(&self, key: usize) -> usize {
+        BIG_STRIDE / *self.priority.get(&key).unwrap_or(&DEFAULT_PRIORITY)
+    }
+}
+
+impl<T: SchedKey> Scheduler<T> for StrideScheduler<T> {
+    fn insert(&mut self, item: T) {
+        let key = item.sched_key();
+        self.priority.entry(key).or_insert(DEFAULT_PRIORITY);
+        self.stride.entry(key).or_insert(0);
+        self.ready.push_back(item);
+    }
+
+    fn peek(&self) -> Option<&T> {
+        // Compare strides as a wrapping (signed) distance rather than with
+        // raw `<`, since strides wrap around modulo `usize::MAX + 1`.
+        self.ready.iter().min_by(|a, b| {
+            let diff = self.stride[&a.sched_key()].wrapping_sub(self.stride[&b.sched_key()]) as isize;
+            diff.cmp(&0)
+        })
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        let best_key = self.peek()?.sched_key();
+        let pos = self.ready.iter().position(|x| x.sched_key() == best_key)?;
+        let item = self.ready.remove(pos)?;
+        let pass = self.pass(best_key);
+        let stride = self.stride.entry(best_key).or_insert(0);
+        *stride = stride.wrapping_add(pass);
+        Some(item)
+    }
+
+    fn remove(&mut self, item: &T) -> Option<T> {
+        let key = item.sched_key();
+        let pos = self.ready.iter().position(|x| x.sched_key() == key)?;
+        self.ready.remove(pos)
+    }
+
+    fn set_priority(&mut self, item: &T, priority: usize) {
+        self.priority.insert(item.sched_key(), priority.max(2));
+    }
+
+    /// Drop `key`'s priority/stride entries. Pids are recycled, so without
+    /// this a new task handed a recycled pid would have its `insert`'s
+    /// `or_insert` silently keep a dead task's leftover priority/stride
+    /// instead of falling back to the documented defaults.
+    fn forget(&mut self, key: usize) {
+        self.priority.remove(&key);
+        self.stride.remove(&key);
+    }
+}
+
+/// Selects which [`Scheduler`] impl [`new_scheduler`] builds. `false` (the
+/// default) keeps stride scheduling active, since that's what makes
+/// `sys_set_priority` have any effect; flip to `true` and rebuild to fall
+/// back to plain FIFO. A plain `const` rather than a Cargo feature, since no
+/// `Cargo.toml` in this workspace declares one to gate on.
+pub const USE_FIFO_SCHEDULER: bool = false;
+
+/// Build the scheduler the task manager should use, per [`USE_FIFO_SCHEDULER`].
+pub fn new_scheduler<T: SchedKey + Send + 'static>() -> Box<dyn Scheduler<T> + Send> {
+    if USE_FIFO_SCHEDULER {
+        Box::new(FifoScheduler::new())
+    } else {
+        Box::new(StrideScheduler::new())
+    }
+}
@@ -0,0 +1,376 @@
+//! Types related to task management
+
+use super::pid::{pid_alloc, KernelStack, PidHandle};
+use super::TaskContext;
+use crate::config::{MAX_SYSCALL_NUM, TRAP_CONTEXT};
+use crate::fs::{File, Stdin, Stdout};
+use crate::mm::{MapPermission, MemorySet, PhysPageNum, VirtAddr, VirtPageNum, KERNEL_SPACE};
+use crate::sync::UPSafeCell;
+use crate::trap::{trap_handler, TrapContext};
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefMut;
+
+/// A single `mmap`-ed virtual memory region belonging to a task, tracked
+/// independently of `MemorySet`'s own area list. Keyed by its start VPN in
+/// `TaskControlBlockInner::mmap_areas`, so overlap detection and splitting
+/// are `O(log n)` in the number of regions instead of `O(pages)`.
+#[derive(Clone)]
+pub struct MmapArea {
+    /// One past the last page mapped by this region
+    pub end_vpn: VirtPageNum,
+    /// The permission bits the region was mapped with
+    pub permission: MapPermission,
+    /// If `true`, `mmap` allocated no frames for this region; each page gets
+    /// its frame from the page-fault handler the first time it is touched.
+    /// The flag stays `true` after some pages have been faulted in.
+    pub lazy: bool,
+}
+
+/// The task control block (TCB) of a task.
+///
+/// `pid` and `kernel_stack` are fixed for the task's lifetime; everything
+/// that changes while it runs lives behind `inner`, deferring borrow checks
+/// to runtime the same way [`super::manager::TaskManager`] does.
+///
+/// Scheduling policy state (priority, stride, run-queue membership) lives in
+/// [`super::manager::TaskManager`], not here, so this struct stays
+/// policy-agnostic.
+pub struct TaskControlBlock {
+    /// Immutable: the task's process id
+    pub pid: PidHandle,
+    /// Immutable: the task's kernel-mode stack
+    pub kernel_stack: KernelStack,
+    /// Mutable state, behind a runtime borrow check
+    inner: UPSafeCell<TaskControlBlockInner>,
+}
+
+/// Everything about a task that can change while it's alive.
+pub struct TaskControlBlockInner {
+    /// The physical page number of the frame that holds the trap context
+    pub trap_cx_ppn: PhysPageNum,
+    /// The size of the application's address space measured in bytes,
+    /// i.e., the length of the code segment plus data segment
+    pub base_size: usize,
+    /// The task's context used in `__switch`
+    pub task_cx: TaskContext,
+    /// The execution status of the task
+    pub task_status: TaskStatus,
+    /// The task's address space
+    pub memory_set: MemorySet,
+    /// The parent task, if any (`None` only for the very first task)
+    pub parent: Option<Weak<TaskControlBlock>>,
+    /// Tasks forked/spawned from this one that haven't yet been waited on
+    pub children: Vec<Arc<TaskControlBlock>>,
+    /// Exit code reported to whichever `waitpid` reaps this task
+    pub exit_code: i32,
+    /// This task's open file descriptors
+    pub fd_table: Vec<Option<Arc<dyn File>>>,
+    /// The bottom of the heap, used by `sys_sbrk`
+    pub heap_bottom: usize,
+    /// The current position of the program break, used by `sys_sbrk`
+    pub program_brk: usize,
+    /// This task's `mmap`-ed regions, keyed by start VPN, kept sorted so
+    /// `mmap`/`munmap` can do a range lookup instead of a page-by-page scan
+    pub mmap_areas: BTreeMap<VirtPageNum, MmapArea>,
+    /// The number of times each syscall has been invoked by this task
+    pub syscall_times: [usize; MAX_SYSCALL_NUM],
+    /// Timestamp (in milliseconds) of the first time this task is scheduled
+    pub task_start_time: usize,
+    /// Total time (in microseconds) this task has spent executing in user
+    /// mode
+    pub user_time: usize,
+    /// Total time (in microseconds) this task has spent executing in kernel
+    /// mode on its own behalf (syscalls, page faults, the scheduler itself)
+    pub kernel_time: usize,
+    /// Timestamp (in microseconds) of the last user/kernel mode boundary or
+    /// context switch this task went through; used to compute how much time
+    /// to attribute to `user_time`/`kernel_time` at the next boundary
+    pub last_checkpoint: usize,
+    /// The user id this task's syscalls are checked against by
+    /// [`easy_fs::check_access`]
+    pub uid: u32,
+    /// The group id this task's syscalls are checked against by
+    /// [`easy_fs::check_access`]
+    pub gid: u32,
+}
+
+impl TaskControlBlockInner {
+    /// Get the address of the trap context
+    pub fn get_trap_cx(&self) -> &'static mut TrapContext {
+        self.trap_cx_ppn.get_mut()
+    }
+    /// Get the token of the task's address space
+    pub fn get_user_token(&self) -> usize {
+        self.memory_set.token()
+    }
+    /// Whether this task has exited and is waiting to be reaped
+    pub fn is_zombie(&self) -> bool {
+        self.task_status == TaskStatus::Zombie
+    }
+    /// Find the lowest-numbered free file descriptor, growing the table if
+    /// every existing slot is in use.
+    pub fn alloc_fd(&mut self) -> usize {
+        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
+            fd
+        } else {
+            self.fd_table.push(None);
+            self.fd_table.len() - 1
+        }
+    }
+}
+
+impl TaskControlBlock {
+    /// Exclusive, runtime-checked access to this task's mutable state.
+    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
+        self.inner.exclusive_access()
+    }
+    /// Get the address of the trap context
+    pub fn get_trap_cx(&self) -> &'static mut TrapContext {
+        self.inner_exclusive_access().get_trap_cx()
+    }
+    /// Get the token of the task's address space
+    pub fn get_user_token(&self) -> usize {
+        self.inner_exclusive_access().get_user_token()
+    }
+    /// This task's process id
+    pub fn getpid(&self) -> usize {
+        self.pid.0
+    }
+    /// Create the very first task control block from `elf_data`. Everything
+    /// after this one is created via [`Self::fork`] or [`Self::spawn`].
+    pub fn new(elf_data: &[u8]) -> Self {
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let trap_cx_ppn = memory_set
+            .translate(VirtAddr::from(TRAP_CONTEXT).into())
+            .unwrap()
+            .ppn();
+        let pid_handle = pid_alloc();
+        let kernel_stack = KernelStack::new(&pid_handle);
+        let kernel_stack_top = kernel_stack.get_top();
+        let task_control_block = Self {
+            pid: pid_handle,
+            kernel_stack,
+            inner: unsafe {
+                UPSafeCell::new(TaskControlBlockInner {
+                    trap_cx_ppn,
+                    base_size: user_sp,
+                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                    task_status: TaskStatus::Ready,
+                    memory_set,
+                    parent: None,
+                    children: Vec::new(),
+                    exit_code: 0,
+                    fd_table: vec![
+                        // fd 0: stdin
+                        Some(Arc::new(Stdin)),
+                        // fd 1: stdout
+                        Some(Arc::new(Stdout)),
+                        // fd 2: stderr, also goes to stdout for now
+                        Some(Arc::new(Stdout)),
+                    ],
+                    heap_bottom: user_sp,
+                    program_brk: user_sp,
+                    mmap_areas: BTreeMap::new(),
+                    syscall_times: [0; MAX_SYSCALL_NUM],
+                    task_start_time: 0,
+                    user_time: 0,
+                    kernel_time: 0,
+                    last_checkpoint: 0,
+                    uid: 0,
+                    gid: 0,
+                })
+            },
+        };
+        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
+        *trap_cx = TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.exclusive_access().token(),
+            kernel_stack_top,
+            trap_handler as usize,
+        );
+        task_control_block
+    }
+    /// Create a child of `self` that is an exact copy: a fresh address space
+    /// with the same contents and a cloned fd table. Registered as a child
+    /// of `self` but not yet added to the run queue; the caller does that.
+    pub fn fork(self: &Arc<TaskControlBlock>) -> Arc<TaskControlBlock> {
+        let mut parent_inner = self.inner_exclusive_access();
+        let memory_set = MemorySet::from_existing_user(&parent_inner.memory_set);
+        let trap_cx_ppn = memory_set
+            .translate(VirtAddr::from(TRAP_CONTEXT).into())
+            .unwrap()
+            .ppn();
+        let pid_handle = pid_alloc();
+        let kernel_stack = KernelStack::new(&pid_handle);
+        let kernel_stack_top = kernel_stack.get_top();
+        let fd_table = parent_inner
+            .fd_table
+            .iter()
+            .map(|fd| fd.as_ref().map(Arc::clone))
+            .collect();
+        let task_control_block = Arc::new(TaskControlBlock {
+            pid: pid_handle,
+            kernel_stack,
+            inner: unsafe {
+                UPSafeCell::new(TaskControlBlockInner {
+                    trap_cx_ppn,
+                    base_size: parent_inner.base_size,
+                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                    task_status: TaskStatus::Ready,
+                    memory_set,
+                    parent: Some(Arc::downgrade(self)),
+                    children: Vec::new(),
+                    exit_code: 0,
+                    fd_table,
+                    heap_bottom: parent_inner.heap_bottom,
+                    program_brk: parent_inner.program_brk,
+                    mmap_areas: parent_inner.mmap_areas.clone(),
+                    syscall_times: [0; MAX_SYSCALL_NUM],
+                    task_start_time: 0,
+                    user_time: 0,
+                    kernel_time: 0,
+                    last_checkpoint: 0,
+                    uid: parent_inner.uid,
+                    gid: parent_inner.gid,
+                })
+            },
+        });
+        parent_inner.children.push(Arc::clone(&task_control_block));
+        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
+        trap_cx.kernel_sp = kernel_stack_top;
+        task_control_block
+    }
+    /// Create a child of `self` that runs `elf_data` from a fresh address
+    /// space, like `fork` immediately followed by `exec` but without ever
+    /// copying the parent's address space. Registered as a child of `self`
+    /// but not yet added to the run queue; the caller does that.
+    pub fn spawn(self: &Arc<TaskControlBlock>, elf_data: &[u8]) -> Arc<TaskControlBlock> {
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let trap_cx_ppn = memory_set
+            .translate(VirtAddr::from(TRAP_CONTEXT).into())
+            .unwrap()
+            .ppn();
+        let pid_handle = pid_alloc();
+        let kernel_stack = KernelStack::new(&pid_handle);
+        let kernel_stack_top = kernel_stack.get_top();
+        let (uid, gid) = {
+            let parent_inner = self.inner_exclusive_access();
+            (parent_inner.uid, parent_inner.gid)
+        };
+        let task_control_block = Arc::new(TaskControlBlock {
+            pid: pid_handle,
+            kernel_stack,
+            inner: unsafe {
+                UPSafeCell::new(TaskControlBlockInner {
+                    trap_cx_ppn,
+                    base_size: user_sp,
+                    task_cx: TaskContext::goto_trap_return(kernel_stack_top),
+                    task_status: TaskStatus::Ready,
+                    memory_set,
+                    parent: Some(Arc::downgrade(self)),
+                    children: Vec::new(),
+                    exit_code: 0,
+                    fd_table: vec![Some(Arc::new(Stdin)), Some(Arc::new(Stdout)), Some(Arc::new(Stdout))],
+                    heap_bottom: user_sp,
+                    program_brk: user_sp,
+                    mmap_areas: BTreeMap::new(),
+                    syscall_times: [0; MAX_SYSCALL_NUM],
+                    task_start_time: 0,
+                    user_time: 0,
+                    kernel_time: 0,
+                    last_checkpoint: 0,
+                    uid,
+                    gid,
+                })
+            },
+        });
+        self.inner_exclusive_access()
+            .children
+            .push(Arc::clone(&task_control_block));
+        let trap_cx = task_control_block.inner_exclusive_access().get_trap_cx();
+        *trap_cx = TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.exclusive_access().token(),
+            kernel_stack_top,
+            trap_handler as usize,
+        );
+        task_control_block
+    }
+    /// Replace `self`'s address space in place with a fresh one running
+    /// `elf_data`, as `exec` does. `pid`/`kernel_stack`/open fds are kept.
+    pub fn exec(&self, elf_data: &[u8]) {
+        let (memory_set, user_sp, entry_point) = MemorySet::from_elf(elf_data);
+        let trap_cx_ppn = memory_set
+            .translate(VirtAddr::from(TRAP_CONTEXT).into())
+            .unwrap()
+            .ppn();
+        let mut inner = self.inner_exclusive_access();
+        inner.memory_set = memory_set;
+        inner.trap_cx_ppn = trap_cx_ppn;
+        inner.base_size = user_sp;
+        inner.heap_bottom = user_sp;
+        inner.program_brk = user_sp;
+        inner.mmap_areas = BTreeMap::new();
+        let kernel_stack_top = self.kernel_stack.get_top();
+        let trap_cx = inner.get_trap_cx();
+        *trap_cx = TrapContext::app_init_context(
+            entry_point,
+            user_sp,
+            KERNEL_SPACE.exclusive_access().token(),
+            kernel_stack_top,
+            trap_handler as usize,
+        );
+    }
+    /// Change the location of the program break, and return the old program
+    /// break's address if successful (`None` if the new break would fall
+    /// below the heap bottom or the heap area could not be resized).
+    pub fn change_program_brk(&self, size: i32) -> Option<usize> {
+        let mut inner = self.inner_exclusive_access();
+        let old_break = inner.program_brk;
+        let new_brk = inner.program_brk as isize + size as isize;
+        if new_brk < inner.heap_bottom as isize {
+            return None;
+        }
+        let heap_bottom = inner.heap_bottom;
+        let result = if size < 0 {
+            inner
+                .memory_set
+                .shrink_to(VirtAddr::from(heap_bottom), VirtAddr::from(new_brk as usize))
+        } else {
+            inner
+                .memory_set
+                .append_to(VirtAddr::from(heap_bottom), VirtAddr::from(new_brk as usize))
+        };
+        if result {
+            inner.program_brk = new_brk as usize;
+            Some(old_break)
+        } else {
+            None
+        }
+    }
+    /// Set this task's scheduling priority (clamped to at least 2: a priority
+    /// of 0 would divide by zero in the stride scheduler's pass, and 1 would
+    /// give a pass of the whole `BIG_STRIDE`).
+    /// Returns the priority actually applied.
+    pub fn set_priority(self: &Arc<TaskControlBlock>, priority: isize) -> isize {
+        let priority = priority.max(2) as usize;
+        super::manager::set_task_priority(self, priority);
+        priority as isize
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+/// The execution status of a task
+pub enum TaskStatus {
+    /// ready to run
+    Ready,
+    /// currently running
+    Running,
+    /// exited, waiting for its parent to collect its exit code
+    Zombie,
+}
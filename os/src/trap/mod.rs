@@ -0,0 +1,162 @@
+//! Trap handling functionality
+//!
+//! All traps go through `__alltraps` in `trap.S`, which saves registers and
+//! calls [`trap_handler`]. `trap_handler` dispatches on `scause` to the
+//! syscall dispatcher, the timer interrupt handler, or the fault handlers
+//! below, then `__restore` returns control to `U` mode.
+
+mod context;
+
+use crate::syscall::syscall;
+use crate::task::{
+    current_trap_cx, current_user_token, exit_current_and_run_next, handle_lazy_page_fault,
+    record_kernel_to_user, record_user_to_kernel, suspend_current_and_run_next, FaultAccess,
+};
+use crate::timer::set_next_trigger;
+use core::arch::{asm, global_asm};
+use riscv::register::{
+    mtvec::TrapMode,
+    scause::{self, Exception, Interrupt, Trap},
+    sie, stval, stvec,
+};
+
+global_asm!(include_str!("trap.S"));
+
+/// Enable timer interrupt in supervisor mode
+pub fn enable_timer_interrupt() {
+    unsafe {
+        sie::set_stimer();
+    }
+}
+
+/// Point `stvec` at [`trap_from_kernel`] while running in the kernel
+fn set_kernel_trap_entry() {
+    unsafe {
+        stvec::write(trap_from_kernel as usize, TrapMode::Direct);
+    }
+}
+
+/// Point `stvec` at `__alltraps` as mapped in the trampoline page, the only
+/// copy of it visible from a user address space
+fn set_user_trap_entry() {
+    unsafe {
+        stvec::write(crate::config::TRAMPOLINE, TrapMode::Direct);
+    }
+}
+
+/// Install the trap handler
+pub fn init() {
+    set_kernel_trap_entry();
+}
+
+#[no_mangle]
+/// Handle a trap from user space.
+pub fn trap_handler() -> ! {
+    set_kernel_trap_entry();
+    // We've just crossed from user mode into the kernel: close out the
+    // segment of time the current task spent running in user mode.
+    record_user_to_kernel();
+    let scause = scause::read();
+    let stval = stval::read();
+    match scause.cause() {
+        Trap::Exception(Exception::UserEnvCall) => {
+            let mut cx = current_trap_cx();
+            cx.sepc += 4;
+            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12]]) as usize;
+            // `cx` may have moved if the syscall changed the address space (e.g. exec)
+            cx = current_trap_cx();
+            cx.x[10] = result;
+        }
+        Trap::Exception(Exception::StorePageFault)
+        | Trap::Exception(Exception::InstructionPageFault)
+        | Trap::Exception(Exception::LoadPageFault) => {
+            // A fault inside a pending (not-yet-faulted-in) lazy `mmap`
+            // region, with an access compatible with the region's
+            // permission bits, is expected: allocate the missing frame and
+            // retry the faulting instruction. Anything else (outside any
+            // tracked region, or a store against a read-only region and the
+            // like) is a genuine bad access.
+            let access = match scause.cause() {
+                Trap::Exception(Exception::StorePageFault) => FaultAccess::Store,
+                Trap::Exception(Exception::InstructionPageFault) => FaultAccess::Instruction,
+                _ => FaultAccess::Load,
+            };
+            if handle_lazy_page_fault(stval, access) {
+                // fall through, re-executing the faulting instruction
+            } else {
+                println!(
+                    "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.",
+                    scause.cause(),
+                    stval,
+                    current_trap_cx().sepc,
+                );
+                exit_current_and_run_next(-2);
+            }
+        }
+        Trap::Exception(Exception::StoreFault)
+        | Trap::Exception(Exception::InstructionFault)
+        | Trap::Exception(Exception::LoadFault) => {
+            // A plain access fault (as opposed to a page fault) never comes
+            // from a pending lazy mapping: every lazy region is backed by a
+            // genuinely absent PTE, which faults as a *PageFault variant,
+            // not this one. Treat it as a bad access directly.
+            println!(
+                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.",
+                scause.cause(),
+                stval,
+                current_trap_cx().sepc,
+            );
+            exit_current_and_run_next(-2);
+        }
+        Trap::Exception(Exception::IllegalInstruction) => {
+            println!("[kernel] IllegalInstruction in application, core dumped.");
+            exit_current_and_run_next(-3);
+        }
+        Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            set_next_trigger();
+            suspend_current_and_run_next();
+        }
+        _ => {
+            panic!(
+                "Unsupported trap {:?}, stval = {:#x}!",
+                scause.cause(),
+                stval
+            );
+        }
+    }
+    trap_return();
+}
+
+#[no_mangle]
+/// Return to user space from the trap handler.
+pub fn trap_return() -> ! {
+    // We're about to cross back from the kernel into user mode: close out
+    // the segment of time the current task spent running in the kernel.
+    record_kernel_to_user();
+    set_user_trap_entry();
+    extern "C" {
+        fn __alltraps();
+        fn __restore();
+    }
+    let trap_cx_ptr = crate::config::TRAP_CONTEXT;
+    let user_satp = current_user_token();
+    let restore_va = __restore as usize - __alltraps as usize + crate::config::TRAMPOLINE;
+    unsafe {
+        asm!(
+            "fence.i",
+            "jr {restore_va}",
+            restore_va = in(reg) restore_va,
+            in("a0") trap_cx_ptr,
+            in("a1") user_satp,
+            options(noreturn)
+        );
+    }
+}
+
+#[no_mangle]
+/// Panic handler for traps taken while already in kernel mode.
+pub fn trap_from_kernel() -> ! {
+    panic!("a trap from kernel should not happen, stval = {:#x}!", stval::read());
+}
+
+pub use context::TrapContext;